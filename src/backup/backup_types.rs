@@ -1,25 +1,145 @@
-use std::path::PathBuf;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::config::config_types::KeySource;
+
+use super::encrypt::ENCRYPTED_EXTENSION;
+
+/// Extensions of backups written as a single file rather than a folder
+pub const BACKUP_EXTENSIONS: &[&str] = &[
+    ".zip.enc",
+    ".tar.gz.enc",
+    ".tar.zst.enc",
+    ".zip",
+    ".tar.gz",
+    ".tar.zst",
+    ".manifest",
+];
+
+/// Appended to a backup's file name to mark it as pinned
+pub const PIN_EXTENSION: &str = ".pinned";
+
+/// Path of the sidecar file marking a backup as pinned
+pub fn pin_path(backup_path: &Path) -> PathBuf {
+    let mut name = OsString::from(backup_path.as_os_str());
+    name.push(PIN_EXTENSION);
+    PathBuf::from(name)
+}
 
 #[derive(Debug, PartialEq)]
 pub struct BackupState {
-    pub last_modified_time: i64,
+    /// Whether the files differ from those recorded for the latest backup
+    pub changed: bool,
     pub latest_backup_time: i64,
-    pub oldest_backup_path: Option<PathBuf>,
     pub backup_count: u64,
 }
 
 impl BackupState {
-    pub fn new(
-        last_modified_time: i64,
-        latest_backup_time: i64,
-        oldest_backup_path: Option<PathBuf>,
-        backup_count: u64,
-    ) -> Self {
+    pub fn new(changed: bool, latest_backup_time: i64, backup_count: u64) -> Self {
         Self {
-            last_modified_time,
+            changed,
             latest_backup_time,
-            oldest_backup_path,
             backup_count,
         }
     }
 }
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BackupKind {
+    Regular,
+    /// Taken automatically before a restore, not subject to rotation
+    Snapshot,
+}
+
+impl BackupKind {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            BackupKind::Regular => "",
+            BackupKind::Snapshot => "_snapshot",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BackupEntry {
+    pub path: PathBuf,
+    pub timestamp: String,
+    pub time: i64,
+    pub kind: BackupKind,
+    /// Where to read the passphrase if the backup is encrypted
+    pub key: Option<KeySource>,
+    /// Pinned backups are never removed by rotation
+    pub pinned: bool,
+}
+
+impl BackupEntry {
+    pub fn new(
+        path: PathBuf,
+        timestamp: String,
+        time: i64,
+        kind: BackupKind,
+        key: Option<KeySource>,
+        pinned: bool,
+    ) -> Self {
+        Self {
+            path,
+            timestamp,
+            time,
+            kind,
+            key,
+            pinned,
+        }
+    }
+
+    /// Whether the backup is subject to rotation and counts towards `count`
+    pub fn is_rotated(&self) -> bool {
+        self.kind == BackupKind::Regular && !self.pinned
+    }
+
+    pub fn pin_path(&self) -> PathBuf {
+        pin_path(&self.path)
+    }
+
+    /// File name of the backup without the encrypted extension
+    pub fn archive_name(&self) -> String {
+        let name = self.path.file_name().unwrap().to_string_lossy();
+        name.strip_suffix(ENCRYPTED_EXTENSION)
+            .unwrap_or(&name)
+            .to_owned()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.path.to_string_lossy().ends_with(ENCRYPTED_EXTENSION)
+    }
+
+    pub fn is_zip(&self) -> bool {
+        self.archive_name().ends_with(".zip")
+    }
+
+    pub fn is_tar(&self) -> bool {
+        let name = self.archive_name();
+        name.ends_with(".tar.gz") || name.ends_with(".tar.zst")
+    }
+
+    pub fn is_store(&self) -> bool {
+        self.path.extension().is_some_and(|x| x == "manifest")
+    }
+
+    /// Name of the format the backup was written in, such as `zip` or `tar.zst`
+    pub fn format_name(&self) -> &'static str {
+        let name = self.archive_name();
+        if self.is_store() {
+            "dedup"
+        } else if name.ends_with(".zip") {
+            "zip"
+        } else if name.ends_with(".tar.gz") {
+            "tar.gz"
+        } else if name.ends_with(".tar.zst") {
+            "tar.zst"
+        } else {
+            "folder"
+        }
+    }
+}
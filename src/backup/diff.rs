@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+
+use crate::{config::config_types::GameConfig, utils::utils::GenericResult};
+
+use super::{
+    manifest::{FileRecord, Manifest},
+    restore::{find_backup, BackupSelector},
+};
+
+/// One side of a comparison
+#[derive(Debug, PartialEq)]
+pub enum DiffSide {
+    Backup(BackupSelector),
+    /// The files currently matched by the game's file list
+    Live,
+}
+
+impl DiffSide {
+    pub fn parse(side: Option<&str>) -> Self {
+        match side {
+            None | Some("live") => DiffSide::Live,
+            Some(selector) => DiffSide::Backup(BackupSelector::parse(Some(selector))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct BackupDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl BackupDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Lists the files added, removed and modified going from `from` to `to`
+pub fn diff_backups(
+    config: &GameConfig,
+    from: &DiffSide,
+    to: &DiffSide,
+) -> GenericResult<BackupDiff> {
+    let from = side_files(config, from)?;
+    let mut to = side_files(config, to)?;
+    let mut diff = BackupDiff::default();
+    for (path, file) in from {
+        match to.remove(&path) {
+            None => diff.removed.push(path),
+            Some(other) if other.size != file.size || other.hash != file.hash => {
+                diff.modified.push(path)
+            }
+            Some(_) => {}
+        }
+    }
+    diff.added.extend(to.into_keys());
+    Ok(diff)
+}
+
+fn side_files(config: &GameConfig, side: &DiffSide) -> GenericResult<BTreeMap<String, FileRecord>> {
+    let manifest = match side {
+        DiffSide::Live => Manifest::from_sources(&config.sources)?,
+        DiffSide::Backup(selector) => {
+            Manifest::from_backup_contents(&find_backup(config, selector)?)?
+        }
+    };
+    Ok(manifest
+        .files
+        .into_iter()
+        .map(|x| (x.path.clone(), x))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        backup::{
+            backup_types::BackupKind, file_data::get_backups, restore::BackupSelector,
+            run::create_backup,
+        },
+        config::config_types::{BackupFormat, GameConfig},
+        utils::{path::test_dir, utils::GenericResult},
+    };
+
+    use super::{diff_backups, BackupDiff, DiffSide};
+
+    #[test]
+    fn test_diff() -> GenericResult<()> {
+        let base = test_dir("diff");
+        fs::create_dir_all(base.join("src/sub"))?;
+        fs::write(base.join("src/same.txt"), "same")?;
+        fs::write(base.join("src/changed.txt"), "first")?;
+        fs::write(base.join("src/sub/removed.txt"), "removed")?;
+        let mut config = GameConfig::test("thing", &base.join("src"), &base.join("dst"));
+        create_backup(&config, BackupKind::Regular)?;
+        let first = get_backups(&config)?[0].timestamp.clone();
+
+        fs::write(base.join("src/changed.txt"), "second")?;
+        fs::remove_file(base.join("src/sub/removed.txt"))?;
+        fs::write(base.join("src/added.txt"), "added")?;
+        config.format = BackupFormat::Folder;
+        create_backup(&config, BackupKind::Regular)?;
+
+        let expected = BackupDiff {
+            added: vec!["added.txt".to_owned()],
+            removed: vec!["sub/removed.txt".to_owned()],
+            modified: vec!["changed.txt".to_owned()],
+        };
+        let from = DiffSide::Backup(BackupSelector::Timestamp(first));
+        let latest = DiffSide::Backup(BackupSelector::Latest);
+        assert_eq!(diff_backups(&config, &from, &latest)?, expected);
+        assert_eq!(diff_backups(&config, &from, &DiffSide::Live)?, expected);
+        assert!(diff_backups(&config, &latest, &DiffSide::Live)?.is_empty());
+
+        // Legacy folder backups without a manifest are hashed directly
+        let latest_path = get_backups(&config)?[1].path.clone();
+        fs::remove_file(latest_path.join(".save-backup-manifest.yaml"))?;
+        fs::write(base.join("src/added.txt"), "changed again")?;
+        assert_eq!(
+            diff_backups(&config, &latest, &DiffSide::Live)?.modified,
+            vec!["added.txt".to_owned()]
+        );
+        Ok(())
+    }
+}
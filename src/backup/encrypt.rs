@@ -0,0 +1,396 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use chacha20poly1305::{
+    aead::{
+        rand_core::RngCore,
+        stream::{EncryptorBE32, NewStream, StreamBE32, StreamPrimitive},
+        KeyInit, OsRng, Payload,
+    },
+    XChaCha20Poly1305,
+};
+use hmac::Hmac;
+use sha2::Sha256;
+
+use crate::{
+    config::config_types::{GameConfig, KeySource},
+    utils::{
+        path::{private_temp_path, temp_path},
+        utils::GenericResult,
+    },
+};
+
+use super::backup_types::BackupEntry;
+
+/// Added after the archive extension of encrypted backups
+pub const ENCRYPTED_EXTENSION: &str = ".enc";
+
+const MAGIC: &[u8; 8] = b"SBENC2\0\0";
+const SALT_SIZE: usize = 16;
+/// XChaCha20 nonce less the 5 bytes STREAM uses for its counter
+const NONCE_SIZE: usize = 19;
+const TAG_SIZE: usize = 16;
+const CHUNK_SIZE: usize = 64 * 1024;
+/// PBKDF2 rounds for new backups, stored in the header so it can be raised later
+#[cfg(not(test))]
+const KDF_ROUNDS: u32 = 100_000;
+#[cfg(test)]
+const KDF_ROUNDS: u32 = 1_000;
+/// Range of rounds accepted from a header, so a damaged or tampered backup can't
+/// make key derivation run for hours
+const MIN_KDF_ROUNDS: u32 = 1_000;
+const MAX_KDF_ROUNDS: u32 = 10_000_000;
+const HEADER_SIZE: usize = MAGIC.len() + 4 + SALT_SIZE + NONCE_SIZE;
+
+/// Reads the passphrase, ignoring trailing whitespace such as a final newline
+pub fn read_key(source: &KeySource) -> GenericResult<Vec<u8>> {
+    let key = match source {
+        KeySource::File(path) => fs::read(path)
+            .map_err(|x| format!("Failed to read key file {}: {}", path.display(), x))?,
+        KeySource::Env(name) => env::var(name)
+            .map_err(|_| format!("Environment variable {} is not set", name))?
+            .into_bytes(),
+    };
+    let end = key
+        .iter()
+        .rposition(|x| !x.is_ascii_whitespace())
+        .map_or(0, |x| x + 1);
+    if end == 0 {
+        return Err("Encryption key is empty".into());
+    }
+    Ok(key[..end].to_vec())
+}
+
+fn cipher(key: &[u8], salt: &[u8], rounds: u32) -> XChaCha20Poly1305 {
+    let mut derived = [0; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(key, salt, rounds, &mut derived);
+    XChaCha20Poly1305::new(&derived.into())
+}
+
+/// Fills `buffer` unless the reader ends first, returning the number of bytes read
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+pub fn encrypt_file(source: &Path, target: &Path, key: &[u8]) -> GenericResult<()> {
+    let mut salt = [0; SALT_SIZE];
+    let mut nonce = [0; NONCE_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let header = [MAGIC.as_slice(), &KDF_ROUNDS.to_le_bytes(), &salt, &nonce].concat();
+
+    let mut reader = BufReader::new(fs::File::open(source)?);
+    let mut writer = BufWriter::new(fs::File::create(target)?);
+    writer.write_all(&header)?;
+
+    let mut encryptor =
+        EncryptorBE32::from_aead(cipher(key, &salt, KDF_ROUNDS), nonce.as_ref().into());
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        // A short chunk marks the end, empty if the size is a multiple of the chunk size
+        let read = read_chunk(&mut reader, &mut buffer)?;
+        if read < CHUNK_SIZE {
+            let chunk = encryptor
+                .encrypt_last(Payload {
+                    msg: &buffer[..read],
+                    aad: &header,
+                })
+                .map_err(|_| "Failed to encrypt backup")?;
+            writer.write_all(&chunk)?;
+            break;
+        }
+        let chunk = encryptor
+            .encrypt_next(Payload {
+                msg: &buffer,
+                aad: &header,
+            })
+            .map_err(|_| "Failed to encrypt backup")?;
+        writer.write_all(&chunk)?;
+    }
+    writer.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// Decrypts an encrypted backup a chunk at a time as it is read. Every chunk can be
+/// decrypted on its own, so seeking within an archive, as reading a zip does, only
+/// decrypts the chunks that are read.
+pub struct DecryptingReader<R: Read + Seek> {
+    reader: R,
+    stream: StreamBE32<XChaCha20Poly1305>,
+    /// Header of the backup, authenticated as the associated data of every chunk
+    aad: Vec<u8>,
+    /// Number of chunks, the last of which is shorter than the rest
+    chunks: u64,
+    /// Size of the decrypted contents
+    size: u64,
+    /// Position in the decrypted contents
+    position: u64,
+    /// Index and contents of the chunk decrypted last
+    chunk: Option<(u64, Vec<u8>)>,
+}
+
+impl DecryptingReader<fs::File> {
+    pub fn open(source: &Path, key: &[u8]) -> GenericResult<Self> {
+        let mut reader = fs::File::open(source)?;
+        let mut header = [0; HEADER_SIZE];
+        let complete = read_chunk(&mut reader, &mut header)? == header.len();
+        if !complete || &header[..MAGIC.len()] != MAGIC {
+            return Err(format!("{} is not an encrypted backup", source.display()).into());
+        }
+        let (rounds, rest) = header[MAGIC.len()..].split_at(4);
+        let (salt, nonce) = rest.split_at(SALT_SIZE);
+        let rounds = u32::from_le_bytes(rounds.try_into()?);
+        let body = reader.seek(SeekFrom::End(0))? - HEADER_SIZE as u64;
+        let stride = (CHUNK_SIZE + TAG_SIZE) as u64;
+        let chunks = body / stride + 1;
+        // Every chunk ends with a tag, so a last chunk shorter than one was cut short
+        if !(MIN_KDF_ROUNDS..=MAX_KDF_ROUNDS).contains(&rounds)
+            || body % stride < TAG_SIZE as u64
+            || chunks > u32::MAX as u64
+        {
+            return Err(format!(
+                "{} has an invalid header or length, the backup is damaged",
+                source.display()
+            )
+            .into());
+        }
+        Ok(Self {
+            reader,
+            stream: StreamBE32::from_aead(cipher(key, salt, rounds), nonce.into()),
+            aad: header.to_vec(),
+            chunks,
+            size: body - chunks * TAG_SIZE as u64,
+            position: 0,
+            chunk: None,
+        })
+    }
+}
+
+impl<R: Read + Seek> DecryptingReader<R> {
+    /// Contents of chunk `index`, decrypting it unless it was the last one decrypted
+    fn load_chunk(&mut self, index: u64) -> io::Result<&[u8]> {
+        if self.chunk.as_ref().map(|x| x.0) != Some(index) {
+            let stride = (CHUNK_SIZE + TAG_SIZE) as u64;
+            self.reader
+                .seek(SeekFrom::Start(HEADER_SIZE as u64 + index * stride))?;
+            let mut buffer = vec![0; CHUNK_SIZE + TAG_SIZE];
+            let read = read_chunk(&mut self.reader, &mut buffer)?;
+            buffer.truncate(read);
+            let last = index + 1 == self.chunks;
+            self.stream
+                .decrypt_in_place(index as u32, last, &self.aad, &mut buffer)
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Failed to decrypt backup, the key is wrong or the backup is damaged",
+                    )
+                })?;
+            self.chunk = Some((index, buffer));
+        }
+        Ok(&self.chunk.as_ref().unwrap().1)
+    }
+}
+
+impl<R: Read + Seek> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size {
+            // Only the last chunk proves nothing was dropped from the end, even when empty
+            self.load_chunk(self.chunks - 1)?;
+            return Ok(0);
+        }
+        let offset = (self.position % CHUNK_SIZE as u64) as usize;
+        let chunk = self.load_chunk(self.position / CHUNK_SIZE as u64)?;
+        let read = buf.len().min(chunk.len() - offset);
+        buf[..read].copy_from_slice(&chunk[offset..offset + read]);
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecryptingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of backup",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Path an archive is written to before `finish_archive` moves it into place.
+///
+/// Archives that will be encrypted are written to a private folder outside `save_dir`,
+/// so plaintext never reaches the backup folder.
+pub fn archive_staging_path(config: &GameConfig, final_path: &Path) -> io::Result<PathBuf> {
+    match config.encrypt {
+        Some(_) => private_temp_path(final_path),
+        None => Ok(temp_path(final_path)),
+    }
+}
+
+/// Moves a complete archive to `final_path`, encrypting it first if configured
+pub fn finish_archive(config: &GameConfig, staging: &Path, final_path: &Path) -> GenericResult<()> {
+    match &config.encrypt {
+        Some(source) => {
+            let staged = TempFile(staging.to_path_buf());
+            let mut encrypted = OsString::from(final_path);
+            encrypted.push(ENCRYPTED_EXTENSION);
+            let encrypted = PathBuf::from(encrypted);
+            encrypt_file(&staged.0, &temp_path(&encrypted), &read_key(source)?)?;
+            fs::rename(temp_path(&encrypted), encrypted)?;
+        }
+        None => fs::rename(staging, final_path)?,
+    }
+    Ok(())
+}
+
+/// Removes a file when dropped
+pub struct TempFile(pub PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Passphrase of an encrypted backup
+pub fn backup_key(backup: &BackupEntry) -> GenericResult<Vec<u8>> {
+    let source = backup.key.as_ref().ok_or_else(|| {
+        format!(
+            "{} is encrypted but no key is configured",
+            backup.path.display()
+        )
+    })?;
+    read_key(source)
+}
+
+/// Archive of a backup opened for reading, decrypted as it is read if it is encrypted
+pub enum ArchiveFile {
+    Plain(fs::File),
+    Encrypted(DecryptingReader<fs::File>),
+}
+
+impl ArchiveFile {
+    pub fn open(backup: &BackupEntry) -> GenericResult<Self> {
+        if backup.is_encrypted() {
+            let reader = DecryptingReader::open(&backup.path, &backup_key(backup)?)?;
+            Ok(ArchiveFile::Encrypted(reader))
+        } else {
+            Ok(ArchiveFile::Plain(fs::File::open(&backup.path)?))
+        }
+    }
+}
+
+impl Read for ArchiveFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ArchiveFile::Plain(file) => file.read(buf),
+            ArchiveFile::Encrypted(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for ArchiveFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            ArchiveFile::Plain(file) => file.seek(pos),
+            ArchiveFile::Encrypted(reader) => reader.seek(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Read, Seek, SeekFrom},
+        path::Path,
+    };
+
+    use crate::utils::{path::test_dir, utils::GenericResult};
+
+    use super::{encrypt_file, DecryptingReader, CHUNK_SIZE, MAGIC};
+
+    fn decrypt(source: &Path, key: &[u8]) -> GenericResult<Vec<u8>> {
+        let mut contents = vec![];
+        DecryptingReader::open(source, key)?.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn test_encrypt() -> GenericResult<()> {
+        let base = test_dir("encrypt");
+        let (plain, encrypted) = (base.join("plain"), base.join("encrypted"));
+
+        for size in [0, 10, CHUNK_SIZE, CHUNK_SIZE * 2 + 10] {
+            let contents: Vec<u8> = (0..size).map(|x| x as u8).collect();
+            fs::write(&plain, &contents)?;
+            encrypt_file(&plain, &encrypted, b"passphrase")?;
+            assert_eq!(decrypt(&encrypted, b"passphrase")?, contents);
+        }
+
+        assert!(decrypt(&encrypted, b"wrong").is_err());
+
+        // Implausible rounds are rejected before deriving the key
+        let contents = fs::read(&encrypted)?;
+        let mut rounds = contents.clone();
+        rounds[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&encrypted, &rounds)?;
+        let error = decrypt(&encrypted, b"passphrase").unwrap_err();
+        assert!(error.to_string().contains("invalid header"), "{}", error);
+
+        // Dropping whole chunks, or part of one, from the end is detected
+        fs::write(&encrypted, &contents[..contents.len() - 26])?;
+        assert!(decrypt(&encrypted, b"passphrase").is_err());
+        fs::write(&encrypted, &contents[..contents.len() - 5])?;
+        assert!(decrypt(&encrypted, b"passphrase").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_seek() -> GenericResult<()> {
+        let base = test_dir("decrypt_seek");
+        let (plain, encrypted) = (base.join("plain"), base.join("encrypted"));
+        let contents: Vec<u8> = (0..CHUNK_SIZE * 3 + 100).map(|x| (x / 7) as u8).collect();
+        fs::write(&plain, &contents)?;
+        encrypt_file(&plain, &encrypted, b"passphrase")?;
+
+        let mut reader = DecryptingReader::open(&encrypted, b"passphrase")?;
+        let mut buffer = [0; 20];
+        // Reads across the boundary between two chunks
+        let start = CHUNK_SIZE * 2 - 10;
+        reader.seek(SeekFrom::Start(start as u64))?;
+        reader.read_exact(&mut buffer)?;
+        assert_eq!(buffer, contents[start..start + 20]);
+
+        let end = contents.len();
+        assert_eq!(reader.seek(SeekFrom::End(-20))?, (end - 20) as u64);
+        reader.read_exact(&mut buffer)?;
+        assert_eq!(buffer, contents[end - 20..]);
+        reader.seek(SeekFrom::Current(-40))?;
+        reader.read_exact(&mut buffer)?;
+        assert_eq!(buffer, contents[end - 40..end - 20]);
+        assert!(reader.seek(SeekFrom::Current(-(end as i64))).is_err());
+        Ok(())
+    }
+}
@@ -1,73 +1,124 @@
+use std::collections::HashSet;
 use std::error;
-use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::Path;
 
-use crate::config::config_types::GameConfig;
-use crate::utils::log::LogExpectResult;
-use crate::utils::path::get_backup_time;
-use crate::utils::utils::time_now;
+use crate::config::config_types::{GameConfig, KeySource};
+use crate::utils::path::{dir_size, get_backup_time};
+use crate::utils::utils::GenericResult;
 
-use super::backup_types::BackupState;
+use super::{
+    backup_types::{
+        pin_path, BackupEntry, BackupKind, BackupState, BACKUP_EXTENSIONS, PIN_EXTENSION,
+    },
+    manifest::{Manifest, STATE_FILE},
+};
 
 pub fn get_backup_state(config: &GameConfig) -> Result<BackupState, Box<dyn error::Error>> {
-    // Check save files
-    let mut last_modified_time = SystemTime::UNIX_EPOCH;
-    for file in config.file_list.into_iter() {
-        let last_modified = file
-            .metadata()
-            .log_expect(format!("Failed to access file {}", file.to_str().unwrap()))
-            .modified()?;
-        if last_modified > last_modified_time {
-            last_modified_time = last_modified;
-        }
-    }
+    // Compare save files against the latest backup
+    let manifest = Manifest::from_sources(&config.sources)?;
+    let changed = match Manifest::read(&config.save_dir.join(STATE_FILE))? {
+        Some(recorded) => !recorded.same_contents(&manifest),
+        None => true,
+    };
 
     let mut latest_backup_time = 0;
-    let mut oldest_backup_path: Option<PathBuf> = None;
-    let mut oldest_backup_time: i64 = time_now();
     let mut backup_count: u64 = 0;
-    for file in config
-        .save_dir
+    for backup in get_backups(config)?
+        .into_iter()
+        .filter(|x| x.kind == BackupKind::Regular)
+    {
+        if backup.time > latest_backup_time {
+            latest_backup_time = backup.time;
+        }
+        if !backup.pinned {
+            backup_count += 1;
+        }
+    }
+    Ok(BackupState::new(changed, latest_backup_time, backup_count))
+}
+
+/// Lists the backups and snapshots for a game in `save_dir`, oldest first
+pub fn get_backups(config: &GameConfig) -> Result<Vec<BackupEntry>, Box<dyn error::Error>> {
+    list_backups(&config.save_dir, &config.name, config.encrypt.as_ref())
+}
+
+/// Lists the backups named after `name` in a directory, oldest first
+pub fn list_backups(
+    save_dir: &Path,
+    name: &str,
+    key: Option<&KeySource>,
+) -> Result<Vec<BackupEntry>, Box<dyn error::Error>> {
+    let mut backups = vec![];
+    // Games that have never been backed up have no directory yet
+    if !save_dir.exists() {
+        return Ok(backups);
+    }
+    for file in save_dir
         .read_dir()
-        .log_expect(format!(
-            "Failed to open directory {}",
-            &config.save_dir.to_str().unwrap()
-        ))
+        .map_err(|x| format!("Failed to open directory {}: {}", save_dir.display(), x))?
         .filter_map(|x| x.ok())
         .filter(|x| {
-            x.to_owned()
-                .file_name()
+            x.file_name()
                 .to_str()
                 .unwrap()
-                .to_owned()
-                .contains(&config.name)
+                .starts_with(&format!("{}_", name))
         })
+        .filter(|x| !x.file_name().to_str().unwrap().ends_with(PIN_EXTENSION))
     {
         let filename_ext = file.file_name();
         let mut filename = filename_ext.to_str().unwrap();
-        if filename.ends_with(".zip") {
-            filename = filename.strip_suffix(".zip").unwrap();
+        for extension in BACKUP_EXTENSIONS {
+            if filename.ends_with(extension) {
+                filename = filename.strip_suffix(extension).unwrap();
+            }
         }
-        let created_time = get_backup_time(&config.name, &filename)?;
-        if created_time < oldest_backup_time {
-            oldest_backup_path = Some(file.path());
-            oldest_backup_time = created_time;
+        let kind = if filename.ends_with(BackupKind::Snapshot.suffix()) {
+            filename = filename
+                .strip_suffix(BackupKind::Snapshot.suffix())
+                .unwrap();
+            BackupKind::Snapshot
+        } else {
+            BackupKind::Regular
+        };
+        let timestamp = filename[(name.len() + 1)..].to_owned();
+        // Stray files that only look like backups are skipped
+        let Ok(created_time) = get_backup_time(name, filename) else {
+            continue;
+        };
+        backups.push(BackupEntry::new(
+            file.path(),
+            timestamp,
+            created_time,
+            kind,
+            key.cloned(),
+            pin_path(&file.path()).exists(),
+        ));
+    }
+    backups.sort_by_key(|x| x.time);
+    Ok(backups)
+}
+
+/// Space taken by a set of backups, counting each stored object once
+pub fn total_size(backups: &[BackupEntry]) -> GenericResult<u64> {
+    let mut size = 0;
+    let mut objects = HashSet::new();
+    for backup in backups {
+        if backup.path.is_dir() {
+            size += dir_size(&backup.path)?;
+            continue;
         }
-        if created_time > latest_backup_time {
-            latest_backup_time = created_time;
+        size += backup.path.metadata()?.len();
+        if backup.is_store() {
+            if let Some(manifest) = Manifest::read(&backup.path)? {
+                for file in manifest.files {
+                    if objects.insert(file.hash) {
+                        size += file.size;
+                    }
+                }
+            }
         }
-        backup_count += 1;
     }
-    Ok(BackupState::new(
-        last_modified_time
-            .duration_since(UNIX_EPOCH)?
-            .as_secs()
-            .try_into()
-            .unwrap(),
-        latest_backup_time,
-        oldest_backup_path,
-        backup_count,
-    ))
+    Ok(size)
 }
 
 #[cfg(test)]
@@ -75,42 +126,59 @@ mod test {
     use std::{fs, path::PathBuf};
 
     use crate::{
-        backup::backup_types::BackupState,
-        config::config_types::{FileList, GameConfig},
-        utils::utils::{system_time_unix, GenericResult},
+        backup::{
+            backup_types::{BackupKind, BackupState},
+            run::create_backup,
+        },
+        config::config_types::GameConfig,
+        utils::{constants::DATE_FORMAT, path::test_dir, utils::GenericResult},
     };
 
-    use super::get_backup_state;
+    use super::{get_backup_state, list_backups};
 
     #[test]
     fn test_backup_state() -> GenericResult<()> {
-        let config = GameConfig {
-            name: "thing".to_owned(),
-            save_dir: PathBuf::from("./test/test_backup/dst"),
-            zip: false,
-            file_list: FileList::new("./test/test_backup/src", None, None),
-            interval: 30,
-            count: 10,
-        };
-        let state = get_backup_state(&config)?;
-        let last_modified_time = system_time_unix(
-            &fs::metadata("test/test_backup/src/sub/another_file.txt")?.modified()?,
-        );
-        let latest_backup_time = system_time_unix(
-            &fs::metadata("test/test_backup/dst/thing_2022-04-15_21-02-01+1000.zip")?.created()?,
-        );
-        let oldest_backup_path = Some(PathBuf::from(
-            r"./test/test_backup/dst\thing_2022-04-15_21-01-41+1000.zip",
-        ));
+        let base = test_dir("state");
+        fs::create_dir_all(base.join("src"))?;
+        fs::create_dir_all(base.join("dst"))?;
+        fs::write(base.join("src/file.txt"), "contents")?;
+        for name in [
+            "thing_2022-04-15_21-01-41+1000.zip",
+            "thing_2022-04-15_21-02-01+1000.zip",
+            "thing_2022-04-15_21-03-00+1000_snapshot.zip",
+        ] {
+            fs::write(base.join("dst").join(name), "")?;
+        }
+        let config = GameConfig::test("thing", &base.join("src"), &base.join("dst"));
+        // Nothing recorded yet, and snapshots are left out
+        let latest_backup_time =
+            chrono::DateTime::parse_from_str("2022-04-15_21-02-01+1000", DATE_FORMAT)?.timestamp();
         assert_eq!(
-            state,
-            BackupState::new(
-                last_modified_time,
-                latest_backup_time,
-                oldest_backup_path,
-                2
-            )
+            get_backup_state(&config)?,
+            BackupState::new(true, latest_backup_time, 2)
         );
+
+        create_backup(&config, BackupKind::Regular)?;
+        let state = get_backup_state(&config)?;
+        assert!(!state.changed);
+        assert!(state.latest_backup_time > latest_backup_time);
+        assert_eq!(state.backup_count, 3);
+
+        // Only the contents count, not the modification time
+        fs::write(base.join("src/file.txt"), "contents")?;
+        assert!(!get_backup_state(&config)?.changed);
+        fs::write(base.join("src/file.txt"), "Contents")?;
+        assert!(get_backup_state(&config)?.changed);
+        fs::write(base.join("src/file.txt"), "contents")?;
+        fs::write(base.join("src/new.txt"), "")?;
+        assert!(get_backup_state(&config)?.changed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_backups_missing_dir() -> GenericResult<()> {
+        let backups = list_backups(&PathBuf::from("test/test_never_backed_up"), "thing", None)?;
+        assert!(backups.is_empty());
         Ok(())
     }
 }
@@ -0,0 +1,36 @@
+use std::{thread, time::Duration};
+
+use crate::{
+    config::config_types::GameConfig,
+    utils::{
+        log::log,
+        process::{is_running, process_system},
+        utils::GenericResult,
+    },
+};
+
+use super::run::run_backup;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Backs up a game whenever its process exits, and when it starts if `backup_on_start` is set
+pub fn start_process_loop(config: &GameConfig, process: &str) -> GenericResult<()> {
+    let mut system = process_system();
+    let mut was_running = is_running(&mut system, process);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let running = is_running(&mut system, process);
+        if running != was_running {
+            log(format!(
+                "Process {} {} for {}",
+                process,
+                if running { "started" } else { "exited" },
+                &config.name
+            ));
+            if !running || config.backup_on_start {
+                run_backup(config)?;
+            }
+        }
+        was_running = running;
+    }
+}
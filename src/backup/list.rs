@@ -0,0 +1,116 @@
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+
+use crate::{config::config_types::GameConfig, utils::utils::GenericResult};
+
+use super::{
+    backup_types::{BackupEntry, BackupKind},
+    file_data::{get_backups, total_size},
+    manifest::Manifest,
+};
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BackupInfo {
+    pub game: String,
+    pub timestamp: String,
+    /// Unix time the backup was taken
+    pub time: i64,
+    pub snapshot: bool,
+    pub format: String,
+    pub encrypted: bool,
+    /// Bytes on disk, including stored objects for deduplicated backups
+    pub size: u64,
+    /// `None` if the manifest is missing or cannot be read
+    pub files: Option<usize>,
+    pub pinned: bool,
+    pub label: Option<String>,
+}
+
+impl BackupInfo {
+    pub fn new(game: &str, backup: &BackupEntry) -> GenericResult<Self> {
+        // Encrypted backups can't be read without their key
+        let manifest = Manifest::from_backup(backup).ok().flatten();
+        Ok(Self {
+            game: game.to_owned(),
+            timestamp: backup.timestamp.clone(),
+            time: backup.time,
+            snapshot: backup.kind == BackupKind::Snapshot,
+            format: backup.format_name().to_owned(),
+            encrypted: backup.is_encrypted(),
+            size: total_size(std::slice::from_ref(backup))?,
+            files: manifest.as_ref().map(|x| x.files.len()),
+            pinned: backup.pinned,
+            label: manifest.and_then(|x| x.label),
+        })
+    }
+
+    /// Local time the backup was taken, for display
+    pub fn local_time(&self) -> String {
+        Local
+            .timestamp_opt(self.time, 0)
+            .unwrap()
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+}
+
+/// Describes the backups and snapshots of a game, oldest first
+pub fn list_backup_info(config: &GameConfig) -> GenericResult<Vec<BackupInfo>> {
+    get_backups(config)?
+        .iter()
+        .map(|x| BackupInfo::new(&config.name, x))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        backup::{
+            backup_types::BackupKind,
+            pin::set_pinned,
+            restore::BackupSelector,
+            run::{create_backup, create_labelled_backup},
+        },
+        config::config_types::{BackupFormat, Compression, GameConfig},
+        utils::{path::test_dir, utils::GenericResult},
+    };
+
+    use super::list_backup_info;
+
+    #[test]
+    fn test_list() -> GenericResult<()> {
+        let base = test_dir("listing");
+        fs::create_dir_all(base.join("src/sub"))?;
+        fs::write(base.join("src/file.txt"), "contents")?;
+        fs::write(base.join("src/sub/other.txt"), "other contents")?;
+        let mut config = GameConfig {
+            format: BackupFormat::Folder,
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst"))
+        };
+        create_backup(&config, BackupKind::Regular)?;
+        config.format = BackupFormat::Zip(Compression::Deflate);
+        create_labelled_backup(&config, BackupKind::Regular, Some("before patch 1.3"))?;
+        set_pinned(&config, &BackupSelector::Latest, true)?;
+
+        let info = list_backup_info(&config)?;
+        assert_eq!(info.len(), 2);
+        assert_eq!(
+            (info[0].format.as_str(), info[0].files, info[0].pinned),
+            ("folder", Some(2), false)
+        );
+        assert_eq!(info[0].label, None);
+        assert!(info[0].size > 22);
+        assert_eq!(
+            (info[1].format.as_str(), info[1].files, info[1].pinned),
+            ("zip", Some(2), true)
+        );
+        assert_eq!(info[1].label.as_deref(), Some("before patch 1.3"));
+
+        let json = serde_json::to_value(&info)?;
+        assert_eq!(json[1]["label"], "before patch 1.3");
+        assert_eq!(json[1]["pinned"], true);
+        Ok(())
+    }
+}
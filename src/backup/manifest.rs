@@ -0,0 +1,265 @@
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::{result::ZipError, ZipArchive};
+
+use crate::{
+    config::config_types::{source_files, FileList, GameConfig, Source},
+    utils::utils::{system_time_unix, GenericResult},
+};
+
+use super::{backup_types::BackupEntry, encrypt::ArchiveFile, tarball::read_tar_manifest};
+
+/// Name of the file in `save_dir` recording the contents of the latest backup
+pub const STATE_FILE: &str = ".last-backup.yaml";
+/// Name of the manifest stored inside zip and folder backups
+pub const MANIFEST_FILE: &str = ".save-backup-manifest.yaml";
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FileRecord {
+    /// Path relative to `root` using forward slashes
+    pub path: String,
+    pub size: u64,
+    /// Unix time the file was last modified
+    #[serde(default)]
+    pub modified: i64,
+    /// Hex encoded SHA-256 of the file contents
+    pub hash: String,
+}
+
+impl FileRecord {
+    pub fn new(
+        relative_path: &Path,
+        source: &Path,
+        size: u64,
+        hash: String,
+    ) -> GenericResult<Self> {
+        Ok(FileRecord {
+            path: relative_path_string(relative_path)?,
+            size,
+            modified: system_time_unix(&source.metadata()?.modified()?),
+            hash,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of save-backup that wrote the manifest
+    #[serde(default)]
+    pub version: String,
+    /// Configuration of the game when the backup was taken
+    #[serde(default)]
+    pub config: Option<GameConfig>,
+    /// Note given to a backup taken on demand
+    #[serde(default)]
+    pub label: Option<String>,
+    pub files: Vec<FileRecord>,
+}
+
+impl Manifest {
+    pub fn new(
+        config: Option<&GameConfig>,
+        mut files: Vec<FileRecord>,
+        label: Option<&str>,
+    ) -> Manifest {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Manifest {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            config: config.cloned(),
+            label: label.map(|x| x.to_owned()),
+            files,
+        }
+    }
+
+    pub fn from_sources(sources: &[Source]) -> GenericResult<Manifest> {
+        let mut files = vec![];
+        for (file_path, relative_path) in source_files(sources) {
+            let size = file_path.metadata()?.len();
+            files.push(FileRecord::new(
+                &relative_path,
+                &file_path,
+                size,
+                hash_file(&file_path)?,
+            )?);
+        }
+        Ok(Manifest::new(None, files, None))
+    }
+
+    /// Whether both manifests list the same paths with the same contents
+    pub fn same_contents(&self, other: &Manifest) -> bool {
+        self.files.len() == other.files.len()
+            && self
+                .files
+                .iter()
+                .zip(&other.files)
+                .all(|(a, b)| a.path == b.path && a.size == b.size && a.hash == b.hash)
+    }
+
+    /// Reads the manifest stored in a backup, `None` for backups taken before manifests existed
+    pub fn from_backup(backup: &BackupEntry) -> GenericResult<Option<Manifest>> {
+        if backup.is_zip() {
+            let mut archive = ZipArchive::new(ArchiveFile::open(backup)?)?;
+            let manifest = match archive.by_name(MANIFEST_FILE) {
+                Ok(file) => Some(serde_yaml::from_reader(file)?),
+                Err(ZipError::FileNotFound) => None,
+                Err(error) => return Err(error.into()),
+            };
+            Ok(manifest)
+        } else if backup.is_tar() {
+            read_tar_manifest(backup)
+        } else if backup.is_store() {
+            Manifest::read(&backup.path)
+        } else {
+            Manifest::read(&backup.path.join(MANIFEST_FILE))
+        }
+    }
+
+    /// Reads the manifest stored in a backup, hashing the contents of backups
+    /// taken before manifests existed instead
+    pub fn from_backup_contents(backup: &BackupEntry) -> GenericResult<Manifest> {
+        if let Some(manifest) = Manifest::from_backup(backup)? {
+            return Ok(manifest);
+        }
+        if backup.path.is_dir() {
+            return Manifest::from_sources(&[Source::new(
+                None,
+                FileList::new(backup.path.to_str().unwrap(), None, None),
+            )]);
+        }
+        if !backup.is_zip() {
+            return Err(format!("Backup {} has no manifest", &backup.timestamp).into());
+        }
+        let mut archive = ZipArchive::new(ArchiveFile::open(backup)?)?;
+        let mut files = vec![];
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if file.is_dir() {
+                continue;
+            }
+            let path = file.name().replace('\\', "/");
+            let (size, hash) = hash_reader(&mut file)?;
+            files.push(FileRecord {
+                path,
+                size,
+                modified: 0,
+                hash,
+            });
+        }
+        Ok(Manifest::new(None, files, None))
+    }
+
+    /// Reads a manifest, returning `None` if none has been written yet
+    pub fn read(path: &Path) -> GenericResult<Option<Manifest>> {
+        match fs::File::open(path) {
+            Ok(file) => Ok(Some(serde_yaml::from_reader(BufReader::new(file))?)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> GenericResult<()> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        serde_yaml::to_writer(&mut writer, self)?;
+        writer.into_inner()?.sync_all()?;
+        Ok(())
+    }
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    Ok(hash_reader(&mut fs::File::open(path)?)?.1)
+}
+
+/// Hashes everything left in a reader, returning the number of bytes read and the hash
+pub fn hash_reader<R: Read>(reader: &mut R) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(reader, &mut hasher)?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+/// Hashes data as it is read through it
+pub struct HashingReader<R: Read> {
+    inner: R,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    /// Returns the number of bytes read and their hash
+    pub fn finish(self) -> (u64, String) {
+        (self.size, format!("{:x}", self.hasher.finalize()))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.size += read as u64;
+        Ok(read)
+    }
+}
+
+pub fn relative_path_string(path: &Path) -> io::Result<String> {
+    Ok(path
+        .to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid file name"))?
+        .replace('\\', "/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        config::config_types::{FileList, Source},
+        utils::{path::test_dir, utils::GenericResult},
+    };
+
+    use super::Manifest;
+
+    #[test]
+    fn test_manifest() -> GenericResult<()> {
+        let base = test_dir("manifest");
+        fs::create_dir_all(base.join("src/sub"))?;
+        fs::write(base.join("src/file.txt"), "content")?;
+        fs::write(base.join("src/sub/another_file.txt"), "")?;
+        let file_list = FileList::new(base.join("src").to_str().unwrap(), None, None);
+
+        let manifest = Manifest::from_sources(&[Source::new(None, file_list)])?;
+        assert_eq!(manifest.version, env!("CARGO_PKG_VERSION"));
+        let paths: Vec<&str> = manifest.files.iter().map(|x| x.path.as_str()).collect();
+        assert_eq!(paths, vec!["file.txt", "sub/another_file.txt"]);
+        assert_eq!(manifest.files[0].size, 7);
+        assert_eq!(
+            manifest.files[1].hash,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let path = base.join("manifest.yaml");
+        assert_eq!(Manifest::read(&path)?, None);
+        manifest.write(&path)?;
+        assert_eq!(Manifest::read(&path)?, Some(manifest.clone()));
+
+        // Touching a file changes its modification time but not its contents
+        let mut touched = manifest.clone();
+        touched.files[0].modified += 60;
+        assert!(manifest.same_contents(&touched));
+        touched.files[0].hash = manifest.files[1].hash.clone();
+        assert!(!manifest.same_contents(&touched));
+        Ok(())
+    }
+}
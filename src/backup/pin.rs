@@ -0,0 +1,40 @@
+use std::{fs, io};
+
+use crate::{
+    config::config_types::GameConfig,
+    utils::{log::log, utils::GenericResult},
+};
+
+use super::{
+    backup_types::BackupEntry,
+    restore::{find_backup, BackupSelector},
+    run::lock_game,
+};
+
+/// Marks a backup as pinned, or unpinned, by creating or removing its sidecar file
+pub fn set_pinned(
+    config: &GameConfig,
+    selector: &BackupSelector,
+    pinned: bool,
+) -> GenericResult<BackupEntry> {
+    // Rotation must not remove the backup before it is pinned
+    let _lock = lock_game(config)?;
+    let mut backup = find_backup(config, selector)?;
+    if pinned {
+        fs::write(backup.pin_path(), "")?;
+    } else {
+        match fs::remove_file(backup.pin_path()) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    backup.pinned = pinned;
+    log(format!(
+        "{} backup {} of {}",
+        if pinned { "Pinned" } else { "Unpinned" },
+        &backup.timestamp,
+        &config.name
+    ));
+    Ok(backup)
+}
@@ -0,0 +1,515 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use zip::ZipArchive;
+
+use crate::{
+    config::config_types::{source_files, source_target, FileList, GameConfig, Source},
+    utils::{log::log, utils::GenericResult},
+};
+
+use super::{
+    backup_types::{BackupEntry, BackupKind},
+    encrypt::ArchiveFile,
+    file_data::get_backups,
+    manifest::{Manifest, MANIFEST_FILE},
+    run::{create_backup, lock_game},
+    store::restore_store_backup,
+    tarball::restore_tar_backup,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum BackupSelector {
+    Latest,
+    /// The most recent snapshot taken before a restore
+    Snapshot,
+    Timestamp(String),
+    /// A snapshot, named by its timestamp followed by `_snapshot`
+    SnapshotAt(String),
+}
+
+impl BackupSelector {
+    pub fn parse(selector: Option<&str>) -> Self {
+        match selector {
+            None | Some("latest") => BackupSelector::Latest,
+            Some("snapshot") => BackupSelector::Snapshot,
+            Some(name) => match name.strip_suffix(BackupKind::Snapshot.suffix()) {
+                Some(timestamp) => BackupSelector::SnapshotAt(timestamp.to_owned()),
+                None => BackupSelector::Timestamp(name.to_owned()),
+            },
+        }
+    }
+}
+
+pub fn find_backup(config: &GameConfig, selector: &BackupSelector) -> GenericResult<BackupEntry> {
+    let backups = get_backups(config)?;
+    let backup = match selector {
+        BackupSelector::Latest => backups
+            .into_iter()
+            .rev()
+            .find(|x| x.kind == BackupKind::Regular),
+        BackupSelector::Snapshot => backups
+            .into_iter()
+            .rev()
+            .find(|x| x.kind == BackupKind::Snapshot),
+        BackupSelector::Timestamp(timestamp) => backups
+            .into_iter()
+            .find(|x| x.kind == BackupKind::Regular && &x.timestamp == timestamp),
+        BackupSelector::SnapshotAt(timestamp) => backups
+            .into_iter()
+            .find(|x| x.kind == BackupKind::Snapshot && &x.timestamp == timestamp),
+    };
+    backup.ok_or_else(|| format!("No backup of {} matching {:?}", &config.name, selector).into())
+}
+
+/// Rolls the files of a game back to the state captured in a backup.
+///
+/// The current files are saved as a snapshot first so the restore can be undone.
+/// Only files inside the configured include/exclude set are written or
+/// removed, anything else under `root` is left alone.
+pub fn restore_backup(config: &GameConfig, selector: &BackupSelector) -> GenericResult<()> {
+    let _lock = lock_game(config)?;
+    let backup = find_backup(config, selector)?;
+    create_backup(config, BackupKind::Snapshot)?;
+    let restored = restore_files(&config.sources, &backup)?;
+
+    // Remove files that did not exist when the backup was taken
+    let stale: Vec<PathBuf> = source_files(&config.sources)
+        .filter(|(_, relative_path)| !restored.contains(relative_path))
+        .map(|(file_path, _)| file_path)
+        .collect();
+    for file in stale {
+        fs::remove_file(file)?;
+    }
+
+    log(format!(
+        "Restored {} from backup {}",
+        &config.name, &backup.timestamp
+    ));
+    Ok(())
+}
+
+/// Copies the files of a backup, or those under `path` inside it, into `target`
+/// without touching the game's own files. Fails before writing anything if a file
+/// would be overwritten.
+pub fn extract_backup(
+    config: &GameConfig,
+    selector: &BackupSelector,
+    target: &Path,
+    path: Option<&str>,
+) -> GenericResult<usize> {
+    let backup = find_backup(config, selector)?;
+    let path = path.map(|x| x.trim_matches('/')).filter(|x| !x.is_empty());
+    if let Some(path) = path {
+        if !Path::new(path)
+            .components()
+            .all(|x| matches!(x, Component::Normal(_)))
+        {
+            return Err(format!("Invalid path {} inside backup", path).into());
+        }
+    }
+    // The whole backup goes into `target`, including the folders of aliased sources.
+    // Used as an alias, `path` selects the files under it by whole components, taken
+    // literally, and they keep their place under `target`.
+    let root = match path {
+        Some(path) => target.join(path),
+        None => target.to_owned(),
+    };
+    let sources = [Source::new(
+        path,
+        FileList::new(root.to_str().ok_or("Invalid target directory")?, None, None),
+    )];
+    let mut selected = 0;
+    for file in Manifest::from_backup_contents(&backup)?.files {
+        let destination = match source_target(&sources, Path::new(&file.path)) {
+            Some(destination) if file.path != MANIFEST_FILE => destination,
+            _ => continue,
+        };
+        if destination.exists() {
+            return Err(format!("{:?} already exists", destination).into());
+        }
+        selected += 1;
+    }
+    if selected == 0 {
+        return Err(format!(
+            "No files in backup {} match {}",
+            &backup.timestamp,
+            path.unwrap_or("")
+        )
+        .into());
+    }
+
+    fs::create_dir_all(target)?;
+    let extracted = restore_files(&sources, &backup)?;
+    log(format!(
+        "Extracted {} files of {} from backup {} to {:?}",
+        extracted.len(),
+        &config.name,
+        &backup.timestamp,
+        target
+    ));
+    Ok(extracted.len())
+}
+
+/// Writes the files of a backup that belong to `sources` under their roots,
+/// returning their paths inside the backup
+fn restore_files(sources: &[Source], backup: &BackupEntry) -> GenericResult<HashSet<PathBuf>> {
+    let restored = if backup.is_zip() {
+        restore_zip_backup(sources, backup)?
+    } else if backup.is_tar() {
+        restore_tar_backup(sources, backup)?
+    } else if backup.is_store() {
+        restore_store_backup(sources, &backup.path)?
+    } else {
+        restore_folder_backup(sources, &backup.path)?
+    };
+
+    // Put back modification times recorded when the backup was taken
+    if let Some(manifest) = Manifest::from_backup(backup)? {
+        for file in manifest.files {
+            let target = match source_target(sources, Path::new(&file.path)) {
+                Some(target) if restored.contains(Path::new(&file.path)) => target,
+                _ => continue,
+            };
+            let modified = UNIX_EPOCH + Duration::from_secs(file.modified.try_into()?);
+            fs::File::options()
+                .write(true)
+                .open(target)?
+                .set_modified(modified)?;
+        }
+    }
+    Ok(restored)
+}
+
+fn restore_zip_backup(sources: &[Source], backup: &BackupEntry) -> GenericResult<HashSet<PathBuf>> {
+    let mut archive = ZipArchive::new(ArchiveFile::open(backup)?)?;
+    let mut restored = HashSet::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let relative_path = file.enclosed_name().map(Path::to_path_buf).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Invalid file name in backup")
+        })?;
+        if relative_path == Path::new(MANIFEST_FILE) {
+            continue;
+        }
+        let target = match source_target(sources, &relative_path) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        fs::create_dir_all(target.parent().unwrap())?;
+        let mut output = fs::File::create(&target)?;
+        io::copy(&mut file, &mut output)?;
+        restored.insert(relative_path);
+    }
+    Ok(restored)
+}
+
+fn restore_folder_backup(
+    sources: &[Source],
+    backup_path: &Path,
+) -> GenericResult<HashSet<PathBuf>> {
+    let backup_list = FileList::new(backup_path.to_str().unwrap(), None, None);
+    let mut restored = HashSet::new();
+    for file_path in backup_list.into_iter() {
+        let relative_path = file_path.strip_prefix(backup_path)?.to_path_buf();
+        if relative_path == Path::new(MANIFEST_FILE) {
+            continue;
+        }
+        let target = match source_target(sources, &relative_path) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::copy(&file_path, &target)?;
+        restored.insert(relative_path);
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::{
+        backup::{
+            backup_types::BackupKind,
+            file_data::{get_backup_state, get_backups},
+            manifest::{Manifest, MANIFEST_FILE},
+            run::{create_backup, remove_snapshots},
+            tarball::open_tar,
+        },
+        config::config_types::{
+            BackupFormat, Compression, FileList, GameConfig, KeySource, Source,
+        },
+        utils::{path::test_dir, utils::GenericResult},
+    };
+
+    use super::{extract_backup, find_backup, restore_backup, BackupSelector};
+
+    fn restore_config(
+        format: BackupFormat,
+        dedup: bool,
+        encrypt: bool,
+    ) -> GenericResult<GameConfig> {
+        let base = test_dir(&format!("restore/{}-{}-{}", format.name(), dedup, encrypt));
+        fs::create_dir_all(base.join("src/sub"))?;
+        fs::write(base.join("key"), "passphrase\n")?;
+        fs::write(base.join("src/file.txt"), "original")?;
+        fs::write(base.join("src/sub/another_file.txt"), "original")?;
+        fs::write(base.join("src/ignored.txt"), "original")?;
+        Ok(GameConfig {
+            sources: vec![Source::new(
+                None,
+                FileList::new(
+                    base.join("src").to_str().unwrap(),
+                    None,
+                    Some(vec!["ignored.txt".to_owned()]),
+                ),
+            )],
+            format,
+            encrypt: encrypt.then(|| KeySource::File(base.join("key"))),
+            dedup,
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst"))
+        })
+    }
+
+    fn test_restore(format: BackupFormat, dedup: bool, encrypt: bool) -> GenericResult<()> {
+        let config = restore_config(format, dedup, encrypt)?;
+        let root = &config.sources[0].file_list.root;
+        create_backup(&config, BackupKind::Regular)?;
+        let manifest = Manifest::from_backup(&get_backups(&config)?[0])?.unwrap();
+        assert_eq!(manifest.config, Some(config.clone()));
+        assert_eq!(manifest.files.len(), 2);
+        let backup = &get_backups(&config)?[0];
+        if backup.is_tar() {
+            // The manifest comes first so listing doesn't read the whole archive
+            let mut archive = open_tar(backup)?;
+            let first = archive.entries()?.next().unwrap()?;
+            assert_eq!(first.path()?, Path::new(MANIFEST_FILE));
+        }
+
+        fs::write(root.join("file.txt"), "changed")?;
+        fs::write(root.join("new_file.txt"), "new")?;
+        fs::write(root.join("ignored.txt"), "changed")?;
+        fs::remove_file(root.join("sub/another_file.txt"))?;
+
+        restore_backup(&config, &BackupSelector::Latest)?;
+        assert_eq!(fs::read_to_string(root.join("file.txt"))?, "original");
+        assert_eq!(
+            fs::read_to_string(root.join("sub/another_file.txt"))?,
+            "original"
+        );
+        assert_eq!(fs::read_to_string(root.join("ignored.txt"))?, "changed");
+        assert!(!root.join("new_file.txt").exists());
+        assert!(!root.join(MANIFEST_FILE).exists());
+
+        // Snapshot is kept out of rotation and can undo the restore
+        assert_eq!(get_backup_state(&config)?.backup_count, 1);
+        let snapshot = find_backup(&config, &BackupSelector::Snapshot)?;
+        let timestamp = snapshot.timestamp.clone();
+        assert_eq!(
+            find_backup(&config, &BackupSelector::SnapshotAt(timestamp.clone()))?,
+            snapshot
+        );
+        // Without the suffix only a regular backup taken in the same second matches
+        assert!(find_backup(&config, &BackupSelector::Timestamp(timestamp))
+            .map_or(true, |x| x.kind == BackupKind::Regular));
+        restore_backup(&config, &BackupSelector::Snapshot)?;
+        assert_eq!(fs::read_to_string(root.join("file.txt"))?, "changed");
+        assert!(root.join("new_file.txt").exists());
+        assert!(!root.join("sub/another_file.txt").exists());
+
+        remove_snapshots(&config)?;
+        assert_eq!(get_backups(&config)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_zip() -> GenericResult<()> {
+        test_restore(BackupFormat::Zip(Compression::Deflate), false, false)
+    }
+
+    #[test]
+    fn test_restore_zip_zstd() -> GenericResult<()> {
+        test_restore(BackupFormat::Zip(Compression::Zstd), false, false)
+    }
+
+    #[test]
+    fn test_restore_tar_gz() -> GenericResult<()> {
+        test_restore(BackupFormat::TarGz, false, false)
+    }
+
+    #[test]
+    fn test_restore_tar_zst() -> GenericResult<()> {
+        test_restore(BackupFormat::TarZst, false, false)
+    }
+
+    #[test]
+    fn test_restore_folder() -> GenericResult<()> {
+        test_restore(BackupFormat::Folder, false, false)
+    }
+
+    #[test]
+    fn test_restore_store() -> GenericResult<()> {
+        test_restore(BackupFormat::Folder, true, false)
+    }
+
+    #[test]
+    fn test_restore_zip_encrypted() -> GenericResult<()> {
+        test_restore(BackupFormat::Zip(Compression::Deflate), false, true)
+    }
+
+    #[test]
+    fn test_restore_tar_encrypted() -> GenericResult<()> {
+        test_restore(BackupFormat::TarZst, false, true)
+    }
+
+    #[test]
+    fn test_extract() -> GenericResult<()> {
+        let config = restore_config(BackupFormat::TarGz, false, false)?;
+        let base = config.save_dir.parent().unwrap().to_owned();
+        // Paths are matched literally, not as patterns or partial names
+        let root = &config.sources[0].file_list.root;
+        for dir in ["[s]ub", "sub2"] {
+            fs::create_dir_all(root.join(dir))?;
+            fs::write(root.join(dir).join("file.txt"), dir)?;
+        }
+        create_backup(&config, BackupKind::Regular)?;
+        fs::write(root.join("file.txt"), "changed")?;
+
+        // A single subdirectory, leaving the game's files alone
+        let target = base.join("extracted");
+        assert_eq!(
+            extract_backup(&config, &BackupSelector::Latest, &target, Some("sub"))?,
+            1
+        );
+        assert_eq!(
+            fs::read_to_string(target.join("sub/another_file.txt"))?,
+            "original"
+        );
+        assert!(!target.join("file.txt").exists());
+        assert_eq!(
+            fs::read_to_string(config.sources[0].file_list.root.join("file.txt"))?,
+            "changed"
+        );
+
+        // Nothing is written if a file would be overwritten
+        assert!(extract_backup(&config, &BackupSelector::Latest, &target, None).is_err());
+        assert!(!target.join("file.txt").exists());
+        for path in ["missing", "[", "../file.txt"] {
+            assert!(extract_backup(&config, &BackupSelector::Latest, &target, Some(path)).is_err());
+        }
+
+        let target = base.join("literal");
+        assert_eq!(
+            extract_backup(&config, &BackupSelector::Latest, &target, Some("[s]ub/"))?,
+            1
+        );
+        assert_eq!(fs::read_to_string(target.join("[s]ub/file.txt"))?, "[s]ub");
+        assert!(!target.join("sub").exists());
+        assert_eq!(
+            extract_backup(
+                &config,
+                &BackupSelector::Latest,
+                &target,
+                Some("sub/another_file.txt")
+            )?,
+            1
+        );
+        assert_eq!(
+            fs::read_to_string(target.join("sub/another_file.txt"))?,
+            "original"
+        );
+
+        let target = base.join("whole");
+        assert_eq!(
+            extract_backup(&config, &BackupSelector::Latest, &target, None)?,
+            4
+        );
+        assert_eq!(fs::read_to_string(target.join("file.txt"))?, "original");
+        assert!(!target.join(MANIFEST_FILE).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_sources() -> GenericResult<()> {
+        for (format, dedup) in [
+            (BackupFormat::Zip(Compression::Deflate), false),
+            (BackupFormat::TarZst, false),
+            (BackupFormat::Folder, false),
+            (BackupFormat::Folder, true),
+        ] {
+            let mut config = restore_config(format, dedup, false)?;
+            let base = config.save_dir.parent().unwrap().to_owned();
+            fs::create_dir_all(base.join("settings"))?;
+            fs::write(base.join("settings/options.ini"), "original")?;
+            config.sources[0].alias = Some("saves".to_owned());
+            config.sources.push(Source::new(
+                Some("settings"),
+                FileList::new(base.join("settings").to_str().unwrap(), None, None),
+            ));
+            create_backup(&config, BackupKind::Regular)?;
+            let backup = &get_backups(&config)?[0];
+            let paths: Vec<String> = Manifest::from_backup(backup)?
+                .unwrap()
+                .files
+                .into_iter()
+                .map(|x| x.path)
+                .collect();
+            assert_eq!(
+                paths,
+                vec![
+                    "saves/file.txt",
+                    "saves/sub/another_file.txt",
+                    "settings/options.ini"
+                ]
+            );
+
+            fs::write(base.join("settings/options.ini"), "changed")?;
+            fs::write(base.join("settings/new.ini"), "new")?;
+            fs::write(base.join("src/file.txt"), "changed")?;
+            restore_backup(
+                &config,
+                &BackupSelector::Timestamp(backup.timestamp.clone()),
+            )?;
+            assert_eq!(
+                fs::read_to_string(base.join("settings/options.ini"))?,
+                "original"
+            );
+            assert!(!base.join("settings/new.ini").exists());
+            assert_eq!(fs::read_to_string(base.join("src/file.txt"))?, "original");
+            assert!(!base.join("src/saves").exists());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_selector() {
+        assert_eq!(BackupSelector::parse(None), BackupSelector::Latest);
+        assert_eq!(
+            BackupSelector::parse(Some("latest")),
+            BackupSelector::Latest
+        );
+        assert_eq!(
+            BackupSelector::parse(Some("snapshot")),
+            BackupSelector::Snapshot
+        );
+        assert_eq!(
+            BackupSelector::parse(Some("2022-04-15_21-02-01+1000")),
+            BackupSelector::Timestamp("2022-04-15_21-02-01+1000".to_owned())
+        );
+        assert_eq!(
+            BackupSelector::parse(Some("2022-04-15_21-02-01+1000_snapshot")),
+            BackupSelector::SnapshotAt("2022-04-15_21-02-01+1000".to_owned())
+        );
+    }
+}
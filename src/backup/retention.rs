@@ -0,0 +1,164 @@
+use std::cmp::Reverse;
+
+use chrono::{Local, TimeZone};
+
+use crate::config::config_types::Retention;
+
+use super::backup_types::BackupEntry;
+
+/// Picks the regular, unpinned backups a retention policy no longer keeps.
+///
+/// The newest `last` backups are kept, along with the newest backup of each of the
+/// latest `hourly` hours, `daily` days, `weekly` weeks and `monthly` months that have
+/// backups. The newest backup is always kept.
+pub fn expired_backups(backups: &[BackupEntry], keep: &Retention) -> Vec<BackupEntry> {
+    let mut newest_first: Vec<&BackupEntry> = backups.iter().filter(|x| x.is_rotated()).collect();
+    newest_first.sort_by_key(|x| Reverse(x.time));
+
+    let mut kept = vec![false; newest_first.len()];
+    let last = keep.last.max(1).try_into().unwrap_or(usize::MAX);
+    kept.iter_mut().take(last).for_each(|x| *x = true);
+
+    // Each period is named by formatting the backup time
+    let periods = [
+        (keep.hourly, "%Y-%m-%d %H"),
+        (keep.daily, "%Y-%m-%d"),
+        (keep.weekly, "%G-%V"),
+        (keep.monthly, "%Y-%m"),
+    ];
+    for (count, format) in periods {
+        let mut remaining = count;
+        let mut previous = None;
+        for (i, backup) in newest_first.iter().enumerate() {
+            if remaining == 0 {
+                break;
+            }
+            let period = Local
+                .timestamp_opt(backup.time, 0)
+                .unwrap()
+                .format(format)
+                .to_string();
+            if previous.as_ref() != Some(&period) {
+                kept[i] = true;
+                remaining -= 1;
+                previous = Some(period);
+            }
+        }
+    }
+
+    newest_first
+        .into_iter()
+        .zip(kept)
+        .filter(|(_, kept)| !kept)
+        .map(|(backup, _)| backup.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::{Local, TimeZone};
+
+    use crate::{
+        backup::backup_types::{BackupEntry, BackupKind},
+        config::config_types::Retention,
+    };
+
+    use super::expired_backups;
+
+    fn backup(day: u32, hour: u32, minute: u32, kind: BackupKind) -> BackupEntry {
+        let time = Local
+            .with_ymd_and_hms(2024, 1, day, hour, minute, 0)
+            .unwrap()
+            .timestamp();
+        let timestamp = format!("{}-{}-{}", day, hour, minute);
+        BackupEntry::new(
+            PathBuf::from(&timestamp),
+            timestamp,
+            time,
+            kind,
+            None,
+            false,
+        )
+    }
+
+    fn expired(backups: &[BackupEntry], keep: Retention) -> Vec<String> {
+        let mut expired: Vec<String> = expired_backups(backups, &keep)
+            .into_iter()
+            .map(|x| x.timestamp)
+            .collect();
+        expired.sort();
+        expired
+    }
+
+    #[test]
+    fn test_retention() {
+        // 2024-01-01 is a Monday, so days 1 to 7 are one week
+        let backups = vec![
+            backup(1, 10, 0, BackupKind::Regular),
+            backup(1, 10, 30, BackupKind::Regular),
+            backup(1, 11, 0, BackupKind::Regular),
+            backup(2, 9, 0, BackupKind::Regular),
+            backup(8, 9, 0, BackupKind::Regular),
+            backup(8, 9, 30, BackupKind::Regular),
+            backup(8, 9, 45, BackupKind::Snapshot),
+        ];
+
+        assert_eq!(
+            expired(
+                &backups,
+                Retention {
+                    last: 3,
+                    ..Default::default()
+                }
+            ),
+            vec!["1-10-0", "1-10-30", "1-11-0"]
+        );
+        assert_eq!(
+            expired(
+                &backups,
+                Retention {
+                    hourly: 3,
+                    ..Default::default()
+                }
+            ),
+            vec!["1-10-0", "1-10-30", "8-9-0"]
+        );
+        assert_eq!(
+            expired(
+                &backups,
+                Retention {
+                    last: 1,
+                    daily: 2,
+                    ..Default::default()
+                }
+            ),
+            vec!["1-10-0", "1-10-30", "1-11-0", "8-9-0"]
+        );
+        assert_eq!(
+            expired(
+                &backups,
+                Retention {
+                    weekly: 5,
+                    ..Default::default()
+                }
+            ),
+            vec!["1-10-0", "1-10-30", "1-11-0", "8-9-0"]
+        );
+        assert_eq!(
+            expired(
+                &backups,
+                Retention {
+                    daily: 1,
+                    monthly: 1,
+                    ..Default::default()
+                }
+            ),
+            vec!["1-10-0", "1-10-30", "1-11-0", "2-9-0", "8-9-0"]
+        );
+
+        // An empty policy still keeps the newest backup
+        assert_eq!(expired(&backups, Retention::default()).len(), 5);
+    }
+}
@@ -1,24 +1,41 @@
-use std::{
-    error::Error,
-    fs,
-    io::{self, BufReader, Read, Write},
-    path::Path,
-    thread,
-    time::Duration,
-};
+use std::{error::Error, fs, io, path::Path, thread, time::Duration};
 
-use zip::{write::FileOptions, ZipWriter};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
 
 use crate::{
-    config::config_types::GameConfig,
+    config::config_types::{
+        source_files, BackupFormat, Compression, GameConfig, RootLimit, Trigger,
+    },
     utils::{
-        constants::DATE_FORMAT,
+        constants::{DATE_FORMAT, TEMP_PREFIX},
+        lock::FileLock,
         log::{log, LogExpectResult},
+        path::{dir_size, sync_file, temp_path},
         utils::{time_now, GenericResult},
     },
 };
 
-use super::{backup_types::BackupState, file_data::get_backup_state};
+use super::{
+    backup_types::{BackupEntry, BackupKind, BACKUP_EXTENSIONS},
+    encrypt::{archive_staging_path, finish_archive},
+    file_data::{get_backup_state, get_backups, list_backups, total_size},
+    game_process::start_process_loop,
+    manifest::{hash_file, FileRecord, HashingReader, Manifest, MANIFEST_FILE, STATE_FILE},
+    retention::expired_backups,
+    store::{collect_garbage, create_store_backup, object_dir, OBJECT_DIR, STORE_EXTENSION},
+    tarball::create_tar_backup,
+    watch::start_watch_loop,
+};
+
+/// File in `save_dir` locked while a game's backups are written or removed
+const LOCK_FILE: &str = ".backup.lock";
+
+/// Waits until no other loop or command, in this process or another, is writing or
+/// removing backups of the game
+pub fn lock_game(config: &GameConfig) -> GenericResult<FileLock> {
+    fs::create_dir_all(&config.save_dir)?;
+    Ok(FileLock::acquire(&config.save_dir.join(LOCK_FILE))?)
+}
 
 pub fn start_backup_loop(config: &GameConfig) -> Result<(), Box<dyn Error>> {
     // Initial check
@@ -26,6 +43,25 @@ pub fn start_backup_loop(config: &GameConfig) -> Result<(), Box<dyn Error>> {
         "Failed to create save directory at {}",
         &config.save_dir.to_str().unwrap()
     )); // Required to check for files
+    remove_temporary_backups(config)?;
+    match &config.process {
+        Some(process) => thread::scope(|scope| {
+            scope.spawn(|| {
+                if let Err(error) = start_process_loop(config, process) {
+                    log(format!("Stopped watching process {}: {}", process, error));
+                }
+            });
+            start_trigger_loop(config)
+        }),
+        None => start_trigger_loop(config),
+    }
+}
+
+fn start_trigger_loop(config: &GameConfig) -> GenericResult<()> {
+    if config.trigger == Trigger::Watch {
+        return start_watch_loop(config);
+    }
+
     let state = get_backup_state(&config)?;
     let elapsed_minutes = (time_now() - state.latest_backup_time) / 60;
     if elapsed_minutes < config.interval {
@@ -37,21 +73,34 @@ pub fn start_backup_loop(config: &GameConfig) -> Result<(), Box<dyn Error>> {
     }
 
     loop {
-        let state = get_backup_state(&config)?;
-
-        // Backup required
-        if state.last_modified_time > state.latest_backup_time {
-            create_backup(&config)?;
-        }
-        if state.backup_count >= config.count {
-            remove_backup(&state)?;
-        }
+        run_backup(config)?;
         thread::sleep(Duration::from_secs(
             (config.interval * 60).try_into().unwrap(),
         ));
     }
 }
 
+/// Backs up a game straight away whether or not its files changed, then rotates old backups
+pub fn backup_now(config: &GameConfig, label: Option<&str>) -> GenericResult<()> {
+    let _lock = lock_game(config)?;
+    create_labelled_backup(config, BackupKind::Regular, label)?;
+    remove_expired_backups(config)?;
+    Ok(())
+}
+
+/// Backs up a game if its files changed since the last backup, then rotates old backups
+pub fn run_backup(config: &GameConfig) -> GenericResult<()> {
+    let _lock = lock_game(config)?;
+    let state = get_backup_state(config)?;
+
+    // Backup required
+    if state.changed {
+        create_backup(config, BackupKind::Regular)?;
+    }
+    remove_expired_backups(config)?;
+    Ok(())
+}
+
 fn path_to_string(path: &Path) -> Result<String, io::Error> {
     Ok(path
         .as_os_str()
@@ -63,102 +112,585 @@ fn path_to_string(path: &Path) -> Result<String, io::Error> {
         .to_owned())
 }
 
-fn remove_backup(state: &BackupState) -> Result<(), io::Error> {
-    let extension = state.oldest_backup_path.as_ref().unwrap().extension();
-    if extension.is_none() {
-        fs::remove_dir_all(state.oldest_backup_path.as_ref().unwrap())?;
-    } else if extension.unwrap() == "zip" {
-        fs::remove_file(state.oldest_backup_path.as_ref().unwrap())?;
+/// Removes the backups no longer kept by the retention policy, then the oldest
+/// backups until the age and size limits hold
+fn remove_expired_backups(config: &GameConfig) -> GenericResult<()> {
+    for backup in expired_backups(&get_backups(config)?, &config.retention()) {
+        delete_backup(&backup.path)?;
+        log(format!("Removed backup {}", &backup.timestamp));
     }
 
+    // The newest backup is always kept
+    loop {
+        let backups: Vec<BackupEntry> = get_backups(config)?
+            .into_iter()
+            .filter(|x| x.is_rotated())
+            .collect();
+        if backups.len() <= 1 {
+            break;
+        }
+        let too_old = config
+            .max_age
+            .is_some_and(|x| backups[0].time < time_now() - x);
+        let too_large = match config.max_total_size {
+            Some(limit) => total_size(&backups)? > limit,
+            None => false,
+        };
+        if !too_old && !too_large {
+            break;
+        }
+        delete_backup(&backups[0].path)?;
+        log(format!(
+            "Removed backup {} over limit",
+            &backups[0].timestamp
+        ));
+    }
+
+    if let Some(limit) = &config.save_root_limit {
+        remove_over_root_limit(limit)?;
+    }
     Ok(())
 }
 
-fn create_backup(config: &GameConfig) -> Result<(), Box<dyn Error>> {
-    let time_format = chrono::offset::Local::now().format(DATE_FORMAT);
-    let backup_string = format!("{}_{}", &config.name, time_format);
+/// Removes the oldest unpinned backups of the games under the top level `save_root`
+/// until they fit in its limit, keeping the newest backup of each game
+fn remove_over_root_limit(limit: &RootLimit) -> GenericResult<()> {
+    let mut size = root_size(limit)?;
+    while size > limit.max_total_size {
+        let mut candidates = vec![];
+        for name in &limit.games {
+            let mut backups: Vec<BackupEntry> =
+                list_backups(&limit.save_root.join(name), name, None)?
+                    .into_iter()
+                    .filter(|x| x.is_rotated())
+                    .collect();
+            backups.pop();
+            candidates.extend(backups);
+        }
+        let Some(oldest) = candidates.into_iter().min_by_key(|x| x.time) else {
+            break;
+        };
+        delete_backup(&oldest.path)?;
+        log(format!(
+            "Removed backup {:?} over save_root limit",
+            &oldest.path
+        ));
 
-    fs::create_dir_all(&config.save_dir)?;
+        // Stop rather than empty every game when nothing removable takes up the space
+        let previous = size;
+        size = root_size(limit)?;
+        if size >= previous {
+            break;
+        }
+    }
+    Ok(())
+}
 
-    // Compression on
-    if config.zip {
-        create_zip_backup(config, &backup_string)?;
+/// Space taken by the games sharing the top level `save_root` and their stored objects
+fn root_size(limit: &RootLimit) -> GenericResult<u64> {
+    let mut size = 0;
+    let dirs = limit.games.iter().map(|x| limit.save_root.join(x));
+    for dir in dirs.chain([limit.save_root.join(OBJECT_DIR)]) {
+        // Games that were never backed up have no folder yet
+        if dir.exists() {
+            size += dir_size(&dir)?;
+        }
+    }
+    Ok(size)
+}
 
-    // Compression off
+fn delete_backup(path: &Path) -> GenericResult<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
     } else {
-        create_folder_backup(config, &backup_string)?;
+        fs::remove_file(path)?;
+        if path.extension().is_some_and(|x| x == STORE_EXTENSION) {
+            collect_garbage(path.parent().unwrap())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the unpinned snapshots taken before restores, returning how many were removed
+pub fn remove_snapshots(config: &GameConfig) -> GenericResult<usize> {
+    let _lock = lock_game(config)?;
+    let snapshots: Vec<BackupEntry> = get_backups(config)?
+        .into_iter()
+        .filter(|x| x.kind == BackupKind::Snapshot && !x.pinned)
+        .collect();
+    for snapshot in &snapshots {
+        delete_backup(&snapshot.path)?;
+    }
+    log(format!(
+        "Removed {} snapshots for {}",
+        snapshots.len(),
+        &config.name
+    ));
+    Ok(snapshots.len())
+}
+
+fn backup_name(config: &GameConfig, kind: BackupKind) -> String {
+    loop {
+        let time_format = chrono::offset::Local::now().format(DATE_FORMAT);
+        let backup_string = format!("{}_{}{}", &config.name, time_format, kind.suffix());
+
+        // Wait for the next second rather than overwrite a backup taken this second
+        let exists = [""].iter().chain(BACKUP_EXTENSIONS).any(|x| {
+            config
+                .save_dir
+                .join(format!("{}{}", &backup_string, x))
+                .exists()
+        });
+        if !exists {
+            return backup_string;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+pub fn create_backup(config: &GameConfig, kind: BackupKind) -> Result<(), Box<dyn Error>> {
+    create_labelled_backup(config, kind, None)
+}
+
+/// Creates a backup with a label recorded in its manifest
+pub fn create_labelled_backup(
+    config: &GameConfig,
+    kind: BackupKind,
+    label: Option<&str>,
+) -> GenericResult<()> {
+    fs::create_dir_all(&config.save_dir)?;
+    let backup_string = backup_name(config, kind);
+
+    // Deduplicated
+    let manifest = if config.dedup {
+        create_store_backup(config, &backup_string, label)?
+    } else {
+        match config.format {
+            BackupFormat::Folder => create_folder_backup(config, &backup_string, label)?,
+            BackupFormat::Zip(compression) => {
+                create_zip_backup(config, &backup_string, compression, label)?
+            }
+            BackupFormat::TarGz | BackupFormat::TarZst => {
+                create_tar_backup(config, &backup_string, label)?
+            }
+        }
+    };
+    if kind == BackupKind::Regular {
+        let state_path = config.save_dir.join(STATE_FILE);
+        manifest.write(&temp_path(&state_path))?;
+        fs::rename(temp_path(&state_path), state_path)?;
     }
     log(format!("Created backup for {}", &config.name));
     Ok(())
 }
 
-fn create_folder_backup(config: &GameConfig, backup_string: &str) -> GenericResult<()> {
-    Ok(for file_path in config.file_list.into_iter() {
-        let relative_path = file_path.strip_prefix(&config.file_list.root)?;
-        let save_dir = config.save_dir.join(&backup_string);
+/// Removes backups of a game left half written by a crash or power loss
+pub fn remove_temporary_backups(config: &GameConfig) -> GenericResult<()> {
+    let _lock = lock_game(config)?;
+    let prefix = format!("{}{}_", TEMP_PREFIX, &config.name);
+    for dir in [config.save_dir.clone(), object_dir(&config.save_dir)] {
+        if !dir.exists() {
+            continue;
+        }
+        for entry in dir.read_dir()?.filter_map(|x| x.ok()) {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            // The timestamp check keeps a game named `a` off the files of a game named `a_b`
+            let is_own = file_name.strip_prefix(&prefix).is_some_and(|x| {
+                let timestamp = x.split('.').next().unwrap_or(x);
+                chrono::DateTime::parse_from_str(timestamp, DATE_FORMAT).is_ok()
+            });
+            if !is_own {
+                continue;
+            }
+            if entry.path().is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+            log(format!("Removed incomplete backup {:?}", entry.path()));
+        }
+    }
+    Ok(())
+}
+
+fn create_folder_backup(
+    config: &GameConfig,
+    backup_string: &str,
+    label: Option<&str>,
+) -> GenericResult<Manifest> {
+    let final_dir = config.save_dir.join(backup_string);
+    let save_dir = temp_path(&final_dir);
+    let mut files = vec![];
+    for (file_path, relative_path) in source_files(&config.sources) {
         let save_path = save_dir.join(&relative_path);
 
         fs::create_dir_all(save_path.parent().unwrap())?;
         fs::File::create(&save_path)?;
-        println!("{:?}", &save_path);
-        fs::copy(&file_path, &save_path)?;
-    })
+        let size = fs::copy(&file_path, &save_path)?;
+        sync_file(&save_path)?;
+        files.push(FileRecord::new(
+            &relative_path,
+            &file_path,
+            size,
+            hash_file(&save_path)?,
+        )?);
+    }
+
+    let manifest = Manifest::new(Some(config), files, label);
+    fs::create_dir_all(&save_dir)?;
+    manifest.write(&save_dir.join(MANIFEST_FILE))?;
+    fs::rename(&save_dir, &final_dir)?;
+    Ok(manifest)
 }
 
-fn create_zip_backup(config: &GameConfig, backup_string: &str) -> GenericResult<()> {
-    let zip_file = fs::File::create(config.save_dir.join(format!("{}.zip", &backup_string)))?;
+fn create_zip_backup(
+    config: &GameConfig,
+    backup_string: &str,
+    compression: Compression,
+    label: Option<&str>,
+) -> GenericResult<Manifest> {
+    let final_path =
+        config
+            .save_dir
+            .join(format!("{}{}", &backup_string, config.format.extension()));
+    let staging = archive_staging_path(config, &final_path)?;
+    let zip_file = fs::File::create(&staging)?;
     let mut writer = ZipWriter::new(zip_file);
-    Ok(for file_path in config.file_list.into_iter() {
-        let options = FileOptions::default();
-        let relative_path = file_path.strip_prefix(&config.file_list.root)?;
-        let file_string = path_to_string(relative_path)?;
+    let method = match compression {
+        Compression::Stored => CompressionMethod::Stored,
+        Compression::Deflate => CompressionMethod::Deflated,
+        Compression::Bzip2 => CompressionMethod::Bzip2,
+        Compression::Zstd => CompressionMethod::Zstd,
+    };
+    let options = FileOptions::default()
+        .compression_method(method)
+        .compression_level(config.level);
+    let mut files = vec![];
+    for (file_path, relative_path) in source_files(&config.sources) {
+        let file_string = path_to_string(&relative_path)?;
 
         writer.start_file(file_string, options)?;
-        let file = fs::File::open(file_path)?;
-        let buffer = BufReader::new(file);
-        let content: Vec<u8> = buffer.bytes().map(|x| x.unwrap()).collect();
+        let mut reader = HashingReader::new(fs::File::open(&file_path)?);
+        io::copy(&mut reader, &mut writer)?;
 
-        writer.write_all(&content)?;
-    })
+        let (size, hash) = reader.finish();
+        files.push(FileRecord::new(&relative_path, &file_path, size, hash)?);
+    }
+
+    let manifest = Manifest::new(Some(config), files, label);
+    writer.start_file(MANIFEST_FILE, options)?;
+    serde_yaml::to_writer(&mut writer, &manifest)?;
+    writer.finish()?.sync_all()?;
+    finish_archive(config, &staging, &final_path)?;
+    Ok(manifest)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{error, path::PathBuf};
+    use std::{error, fs, path::Path, time::Instant};
 
     use crate::{
-        backup::file_data::get_backup_state,
-        config::config_types::{FileList, GameConfig},
+        backup::{
+            backup_types::BackupKind,
+            file_data::{get_backup_state, get_backups},
+            manifest::Manifest,
+            pin::set_pinned,
+            restore::BackupSelector,
+        },
+        config::config_types::{BackupFormat, FileList, GameConfig, RootLimit},
+        utils::path::test_dir,
     };
 
-    use super::{create_backup, remove_backup};
+    use super::{
+        backup_now, create_backup, remove_expired_backups, remove_temporary_backups, run_backup,
+    };
 
     #[test]
     fn test_backup() -> Result<(), Box<dyn error::Error>> {
+        let config = GameConfig::test(
+            "thing",
+            Path::new("test/test_backup/src"),
+            &test_dir("backup"),
+        );
+        create_backup(&config, BackupKind::Regular)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove() -> Result<(), Box<dyn error::Error>> {
+        let config = GameConfig::test(
+            "thing",
+            Path::new("test/test_backup/src"),
+            &test_dir("remove"),
+        );
+        remove_expired_backups(&config)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_on_change() -> Result<(), Box<dyn error::Error>> {
+        let base = test_dir("change");
+        fs::create_dir_all(base.join("src"))?;
+        fs::create_dir_all(base.join("dst"))?;
+        fs::write(base.join("src/file.txt"), "first")?;
+        let config = GameConfig::test("thing", &base.join("src"), &base.join("dst"));
+        run_backup(&config)?;
+        run_backup(&config)?;
+        assert_eq!(get_backups(&config)?.len(), 1);
+
+        // Same size and modification time, different content
+        let modified = fs::metadata(base.join("src/file.txt"))?.modified()?;
+        fs::write(base.join("src/file.txt"), "other")?;
+        fs::File::options()
+            .write(true)
+            .open(base.join("src/file.txt"))?
+            .set_modified(modified)?;
+        run_backup(&config)?;
+        assert_eq!(get_backups(&config)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_temporary_backups() -> Result<(), Box<dyn error::Error>> {
+        let base = test_dir("temporary");
+        fs::create_dir_all(base.join("src"))?;
+        fs::create_dir_all(base.join("dst/thing/.tmp-thing_2022-04-15_21-01-41+1000"))?;
+        fs::write(
+            base.join("dst/thing/.tmp-thing_2022-04-15_21-02-01+1000.zip"),
+            "",
+        )?;
+        // Objects being written for other games are left alone
+        fs::create_dir_all(base.join("dst/.objects"))?;
+        fs::write(
+            base.join("dst/.objects/.tmp-thing_2022-04-15_21-02-01+1000"),
+            "",
+        )?;
+        fs::write(
+            base.join("dst/.objects/.tmp-thing_other_2022-04-15_21-02-01+1000"),
+            "",
+        )?;
+        fs::write(
+            base.join("dst/.objects/.tmp-other_2022-04-15_21-02-01+1000"),
+            "",
+        )?;
+        let config = GameConfig::test("thing", &base.join("src"), &base.join("dst/thing"));
+        // Partial backups are never mistaken for complete ones
+        assert_eq!(get_backups(&config)?.len(), 0);
+
+        create_backup(&config, BackupKind::Regular)?;
+        remove_temporary_backups(&config)?;
+        // The backup, the state file and the lock file
+        assert_eq!(fs::read_dir(&config.save_dir)?.count(), 3);
+        assert_eq!(get_backups(&config)?.len(), 1);
+        let mut objects: Vec<String> = fs::read_dir(base.join("dst/.objects"))?
+            .map(|x| x.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        objects.sort();
+        assert_eq!(
+            objects,
+            vec![
+                ".tmp-other_2022-04-15_21-02-01+1000",
+                ".tmp-thing_other_2022-04-15_21-02-01+1000"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_and_age_limits() -> Result<(), Box<dyn error::Error>> {
+        let base = test_dir("limits");
+        fs::create_dir_all(base.join("src"))?;
+        fs::create_dir_all(base.join("dst/thing/thing_2022-04-15_21-04-00+1000"))?;
+        fs::create_dir_all(base.join("dst/other"))?;
+        let contents = [0; 100];
+        for name in [
+            "thing/thing_2022-04-15_21-01-00+1000.zip",
+            "thing/thing_2022-04-15_21-02-00+1000.zip",
+            "thing/thing_2022-04-15_21-03-00+1000.zip",
+            "thing/thing_2022-04-15_21-04-00+1000/file.txt",
+        ] {
+            fs::write(base.join("dst").join(name), contents)?;
+        }
+        let mut config = GameConfig {
+            count: 10,
+            max_total_size: Some(250),
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst/thing"))
+        };
+        let timestamps = |config: &GameConfig| -> Result<Vec<String>, Box<dyn error::Error>> {
+            Ok(get_backups(config)?
+                .into_iter()
+                .map(|x| x.timestamp)
+                .collect())
+        };
+
+        // Every oldest backup over the limit is removed in one go
+        remove_expired_backups(&config)?;
+        assert_eq!(
+            timestamps(&config)?,
+            vec!["2022-04-15_21-03-00+1000", "2022-04-15_21-04-00+1000"]
+        );
+
+        // The newest backup of each game is kept while bringing the save root under its limit
+        fs::write(
+            base.join("dst/other/other_2022-04-15_20-00-00+1000.zip"),
+            contents,
+        )?;
+        fs::write(
+            base.join("dst/other/other_2022-04-15_20-30-00+1000.zip"),
+            contents,
+        )?;
+        // Folders of games no longer configured are neither counted nor removed
+        fs::create_dir_all(base.join("dst/old"))?;
+        for name in [
+            "old/old_2022-04-15_19-00-00+1000.zip",
+            "old/old_2022-04-15_19-30-00+1000.zip",
+        ] {
+            fs::write(base.join("dst").join(name), contents)?;
+        }
+        config.save_root_limit = Some(RootLimit {
+            save_root: base.join("dst"),
+            max_total_size: 150,
+            games: vec!["thing".to_owned(), "other".to_owned()],
+        });
+        remove_expired_backups(&config)?;
+        assert_eq!(timestamps(&config)?, vec!["2022-04-15_21-04-00+1000"]);
+        assert_eq!(fs::read_dir(base.join("dst/other"))?.count(), 1);
+        assert_eq!(fs::read_dir(base.join("dst/old"))?.count(), 2);
+
+        // Stops once only the newest backups are left, even if still over the limit
+        config.save_root_limit = Some(RootLimit {
+            max_total_size: 1,
+            ..config.save_root_limit.unwrap()
+        });
+        remove_expired_backups(&config)?;
+        assert_eq!(timestamps(&config)?, vec!["2022-04-15_21-04-00+1000"]);
+        assert_eq!(fs::read_dir(base.join("dst/other"))?.count(), 1);
+
+        // A game with its own save_root trims the top level save root, not its own
+        fs::write(
+            base.join("dst/other/other_2022-04-15_19-00-00+1000.zip"),
+            contents,
+        )?;
+        let own = GameConfig {
+            save_root_limit: config.save_root_limit.clone(),
+            ..GameConfig::test("own", &base.join("src"), &base.join("own/own"))
+        };
+        remove_expired_backups(&own)?;
+        assert_eq!(fs::read_dir(base.join("dst/other"))?.count(), 1);
+
+        config.max_age = Some(90 * 24 * 60 * 60);
+        config.save_root_limit = None;
+        create_backup(&config, BackupKind::Regular)?;
+        remove_expired_backups(&config)?;
+        assert_eq!(get_backups(&config)?.len(), 1);
+        assert!(!base
+            .join("dst/thing/thing_2022-04-15_21-04-00+1000")
+            .exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_backups() -> Result<(), Box<dyn error::Error>> {
+        let base = test_dir("pin");
+        fs::create_dir_all(base.join("src"))?;
+        fs::create_dir_all(base.join("dst"))?;
+        fs::write(base.join("src/file.txt"), "contents")?;
+        for name in [
+            "thing_2022-04-15_21-01-00+1000.zip",
+            "thing_2022-04-15_21-02-00+1000.zip",
+            "thing_2022-04-15_21-03-00+1000.zip",
+        ] {
+            fs::write(base.join("dst").join(name), [0; 100])?;
+        }
         let config = GameConfig {
-            count: 3,
-            file_list: FileList::new("test/test_backup/src", None, None),
-            interval: 30,
-            name: "thing".to_owned(),
-            save_dir: PathBuf::from("test/test_backup/dst"),
-            zip: true,
+            count: 1,
+            max_total_size: Some(150),
+            max_age: Some(60),
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst"))
         };
-        create_backup(&config)?;
+        let oldest = BackupSelector::Timestamp("2022-04-15_21-01-00+1000".to_owned());
+        assert!(set_pinned(&config, &oldest, true)?.pinned);
+        assert!(base
+            .join("dst/thing_2022-04-15_21-01-00+1000.zip.pinned")
+            .exists());
+        assert_eq!(get_backup_state(&config)?.backup_count, 2);
+
+        // Pinned backups survive rotation and the limits without taking the newest's place
+        remove_expired_backups(&config)?;
+        let backups = get_backups(&config)?;
+        assert_eq!(backups.len(), 2);
+        assert!(backups[0].pinned);
+        assert_eq!(backups[1].timestamp, "2022-04-15_21-03-00+1000");
+
+        set_pinned(&config, &oldest, false)?;
+        remove_expired_backups(&config)?;
+        assert_eq!(get_backups(&config)?.len(), 1);
         Ok(())
     }
 
     #[test]
-    fn test_remove() -> Result<(), Box<dyn error::Error>> {
+    fn test_backup_now_label() -> Result<(), Box<dyn error::Error>> {
+        let base = test_dir("label");
+        fs::create_dir_all(base.join("src"))?;
+        fs::write(base.join("src/file.txt"), "contents")?;
         let config = GameConfig {
-            count: 3,
-            file_list: FileList::new("test/test_backup/src", None, None),
-            interval: 30,
-            name: "thing".to_owned(),
-            save_dir: PathBuf::from("test/test_backup/dst"),
-            zip: true,
+            format: BackupFormat::TarGz,
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst"))
         };
-        let state = get_backup_state(&config)?;
-        remove_backup(&state)?;
+
+        // Backups are taken on demand even when nothing changed
+        backup_now(&config, None)?;
+        backup_now(&config, Some("before patch 1.3"))?;
+        let labels: Vec<Option<String>> = get_backups(&config)?
+            .iter()
+            .map(|x| Ok(Manifest::from_backup(x)?.unwrap().label))
+            .collect::<Result<_, Box<dyn error::Error>>>()?;
+        assert_eq!(labels, vec![None, Some("before patch 1.3".to_owned())]);
+        Ok(())
+    }
+
+    /// Throughput of zip backups over the test fixtures plus a large generated save.
+    ///
+    /// Run with `cargo test --release bench_zip_backup -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_zip_backup() -> Result<(), Box<dyn error::Error>> {
+        let base = test_dir("bench");
+        let fixtures = FileList::new("test/test_full/src", None, None);
+        for file_path in fixtures.into_iter() {
+            let save_path = base
+                .join("src")
+                .join(file_path.strip_prefix(&fixtures.root)?);
+            fs::create_dir_all(save_path.parent().unwrap())?;
+            fs::copy(&file_path, save_path)?;
+        }
+
+        // Partly compressible data, like most save files
+        let mut state: u32 = 1;
+        let large: Vec<u8> = (0..64 * 1024 * 1024)
+            .map(|i| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if i % 4 == 0 {
+                    (state >> 24) as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+        fs::write(base.join("src/large.sav"), &large)?;
+
+        let config = GameConfig::test("thing", &base.join("src"), &base.join("dst"));
+        let start = Instant::now();
+        create_backup(&config, BackupKind::Regular)?;
+        let elapsed = start.elapsed().as_secs_f64();
+        println!(
+            "Zipped {} MiB in {:.2}s ({:.1} MiB/s)",
+            large.len() / 1024 / 1024,
+            elapsed,
+            large.len() as f64 / elapsed / 1024.0 / 1024.0
+        );
+        fs::remove_dir_all(&base)?;
         Ok(())
     }
 }
@@ -0,0 +1,234 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    config::config_types::{source_files, source_target, GameConfig, Source},
+    utils::{
+        lock::FileLock,
+        log::log,
+        path::{sync_file, temp_path},
+        utils::GenericResult,
+    },
+};
+
+use super::manifest::{hash_file, FileRecord, Manifest};
+
+/// Directory under `save_root` holding file contents shared by every deduplicated backup
+pub const OBJECT_DIR: &str = ".objects";
+/// File in the object store locked while objects are added or removed, so garbage
+/// collection never removes an object a backup in another process is about to reference
+const OBJECT_LOCK_FILE: &str = ".lock";
+pub const STORE_EXTENSION: &str = "manifest";
+
+pub fn object_dir(save_dir: &Path) -> PathBuf {
+    save_dir.parent().unwrap_or(save_dir).join(OBJECT_DIR)
+}
+
+/// Path of the object holding the contents with `hash`, checking it is a SHA-256 digest
+/// so a damaged manifest can't point outside the store
+pub fn object_path(object_dir: &Path, hash: &str) -> GenericResult<PathBuf> {
+    if hash.len() != 64 || !hash.bytes().all(|x| matches!(x, b'0'..=b'9' | b'a'..=b'f')) {
+        return Err(format!("Invalid object hash {:?} in backup", hash).into());
+    }
+    Ok(object_dir.join(&hash[..2]).join(&hash[2..]))
+}
+
+/// Copies each file into the object store unless identical content is already there,
+/// then writes a manifest referencing the stored objects as the backup
+pub fn create_store_backup(
+    config: &GameConfig,
+    backup_string: &str,
+    label: Option<&str>,
+) -> GenericResult<Manifest> {
+    let objects = object_dir(&config.save_dir);
+    fs::create_dir_all(&objects)?;
+    let _lock = FileLock::acquire(&objects.join(OBJECT_LOCK_FILE))?;
+    let incoming = temp_path(&objects.join(backup_string));
+
+    let mut files = vec![];
+    for (file_path, relative_path) in source_files(&config.sources) {
+        let mut hash = hash_file(&file_path)?;
+        if !object_path(&objects, &hash)?.exists() {
+            // Hash the copy in case the file changed after it was first hashed
+            fs::copy(&file_path, &incoming)?;
+            sync_file(&incoming)?;
+            hash = hash_file(&incoming)?;
+            let object = object_path(&objects, &hash)?;
+            fs::create_dir_all(object.parent().unwrap())?;
+            fs::rename(&incoming, object)?;
+        }
+        let size = object_path(&objects, &hash)?.metadata()?.len();
+        files.push(FileRecord::new(&relative_path, &file_path, size, hash)?);
+    }
+
+    let manifest = Manifest::new(Some(config), files, label);
+    let manifest_path = config
+        .save_dir
+        .join(format!("{}.{}", backup_string, STORE_EXTENSION));
+    manifest.write(&temp_path(&manifest_path))?;
+    fs::rename(temp_path(&manifest_path), &manifest_path)?;
+    Ok(manifest)
+}
+
+pub fn restore_store_backup(
+    sources: &[Source],
+    backup_path: &Path,
+) -> GenericResult<HashSet<PathBuf>> {
+    let objects = object_dir(backup_path.parent().unwrap());
+    let manifest = Manifest::read(backup_path)?.ok_or("Backup manifest is missing")?;
+    let mut restored = HashSet::new();
+    for file in manifest.files {
+        let relative_path = PathBuf::from(&file.path);
+        if !relative_path
+            .components()
+            .all(|x| matches!(x, Component::Normal(_)))
+        {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "Invalid file name in backup").into(),
+            );
+        }
+        let target = match source_target(sources, &relative_path) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::copy(object_path(&objects, &file.hash)?, &target)?;
+        restored.insert(relative_path);
+    }
+    Ok(restored)
+}
+
+/// Removes objects no longer referenced by any manifest under `save_root`
+pub fn collect_garbage(save_dir: &Path) -> GenericResult<()> {
+    let objects = object_dir(save_dir);
+    let save_root = save_dir.parent().unwrap_or(save_dir);
+    let _lock = FileLock::acquire(&objects.join(OBJECT_LOCK_FILE))?;
+
+    let mut referenced = HashSet::new();
+    for game_dir in save_root.read_dir()?.filter_map(|x| x.ok()) {
+        if !game_dir.path().is_dir() {
+            continue;
+        }
+        for file in game_dir.path().read_dir()?.filter_map(|x| x.ok()) {
+            let path = file.path();
+            if path.extension().is_some_and(|x| x == STORE_EXTENSION) {
+                if let Some(manifest) = Manifest::read(&path)? {
+                    referenced.extend(manifest.files.into_iter().map(|x| x.hash));
+                }
+            }
+        }
+    }
+
+    let mut removed = 0;
+    for prefix in objects.read_dir()?.filter_map(|x| x.ok()) {
+        if !prefix.path().is_dir() {
+            continue;
+        }
+        for object in prefix.path().read_dir()?.filter_map(|x| x.ok()) {
+            let hash = format!(
+                "{}{}",
+                prefix.file_name().to_string_lossy(),
+                object.file_name().to_string_lossy()
+            );
+            if !referenced.contains(&hash) {
+                fs::remove_file(object.path())?;
+                removed += 1;
+            }
+        }
+    }
+    log(format!("Removed {} unreferenced objects", removed));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        backup::{
+            backup_types::BackupKind, file_data::get_backups, manifest::Manifest,
+            run::create_backup,
+        },
+        config::config_types::{BackupFormat, GameConfig},
+        utils::{path::test_dir, utils::GenericResult},
+    };
+
+    use super::{collect_garbage, object_path, restore_store_backup};
+
+    fn count_objects(objects: &PathBuf) -> GenericResult<usize> {
+        let mut count = 0;
+        for prefix in fs::read_dir(objects)? {
+            let prefix = prefix?.path();
+            if prefix.is_dir() {
+                count += fs::read_dir(prefix)?.count();
+            }
+        }
+        Ok(count)
+    }
+
+    #[test]
+    fn test_store_dedup() -> GenericResult<()> {
+        let base = test_dir("store");
+        fs::create_dir_all(base.join("src"))?;
+        fs::write(base.join("src/file.txt"), "first")?;
+        fs::write(base.join("src/same.txt"), "same")?;
+        fs::write(base.join("src/copy.txt"), "same")?;
+        let config = GameConfig {
+            format: BackupFormat::Folder,
+            dedup: true,
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst/thing"))
+        };
+        let objects = base.join("dst/.objects");
+
+        create_backup(&config, BackupKind::Regular)?;
+        assert_eq!(count_objects(&objects)?, 2);
+
+        fs::write(base.join("src/file.txt"), "second")?;
+        create_backup(&config, BackupKind::Regular)?;
+        assert_eq!(count_objects(&objects)?, 3);
+
+        // Only the first version of file.txt is unreferenced once the first backup is gone
+        fs::remove_file(&get_backups(&config)?[0].path)?;
+        collect_garbage(&config.save_dir)?;
+        assert_eq!(count_objects(&objects)?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_damaged_manifest() -> GenericResult<()> {
+        let base = test_dir("store_damaged");
+        fs::create_dir_all(base.join("src/sub"))?;
+        fs::write(base.join("src/sub/file.txt"), "contents")?;
+        let config = GameConfig {
+            dedup: true,
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst/thing"))
+        };
+        create_backup(&config, BackupKind::Regular)?;
+        let backup = get_backups(&config)?.remove(0);
+        let original = Manifest::read(&backup.path)?.unwrap();
+
+        // Paths leaving the root and hashes that aren't digests are rejected
+        for (path, hash) in [
+            ("../../escaped.txt", original.files[0].hash.clone()),
+            ("sub/file.txt", "a".to_owned()),
+            ("sub/file.txt", "é".repeat(32)),
+            (
+                "sub/file.txt",
+                format!("../{}", &original.files[0].hash[3..]),
+            ),
+        ] {
+            let mut manifest = original.clone();
+            manifest.files[0].path = path.to_owned();
+            manifest.files[0].hash = hash;
+            manifest.write(&backup.path)?;
+            assert!(restore_store_backup(&config.sources, &backup.path).is_err());
+        }
+        assert!(!base.join("escaped.txt").exists());
+        assert!(object_path(&base, &original.files[0].hash).is_ok());
+        Ok(())
+    }
+}
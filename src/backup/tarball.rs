@@ -0,0 +1,159 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder};
+use tar::{Archive, Builder, Header};
+
+use crate::{
+    config::config_types::{source_files, source_target, BackupFormat, GameConfig, Source},
+    utils::utils::{time_now, GenericResult},
+};
+
+use super::{
+    backup_types::BackupEntry,
+    encrypt::{archive_staging_path, backup_key, finish_archive, DecryptingReader, TempFile},
+    manifest::{FileRecord, HashingReader, Manifest, MANIFEST_FILE},
+};
+
+/// Writes the files and manifest of a backup into a compressed tar archive
+pub fn create_tar_backup(
+    config: &GameConfig,
+    backup_string: &str,
+    label: Option<&str>,
+) -> GenericResult<Manifest> {
+    let final_path =
+        config
+            .save_dir
+            .join(format!("{}{}", backup_string, config.format.extension()));
+    let staging = archive_staging_path(config, &final_path)?;
+    let file = fs::File::create(&staging)?;
+    let (file, manifest) = if config.format == BackupFormat::TarZst {
+        let encoder = zstd::Encoder::new(file, config.level.unwrap_or(0))?;
+        let (encoder, manifest) = write_tar(config, encoder, &staging, label)?;
+        (encoder.finish()?, manifest)
+    } else {
+        let level = match config.level {
+            Some(level) => flate2::Compression::new(level.try_into()?),
+            None => flate2::Compression::default(),
+        };
+        let (encoder, manifest) = write_tar(config, GzEncoder::new(file, level), &staging, label)?;
+        (encoder.finish()?, manifest)
+    };
+    file.sync_all()?;
+    finish_archive(config, &staging, &final_path)?;
+    Ok(manifest)
+}
+
+/// Writes the manifest as the first entry so reading it doesn't need the rest of the
+/// archive. The files go into an uncompressed tar next to `staging` first, as the
+/// manifest holds their hashes.
+fn write_tar<W: Write>(
+    config: &GameConfig,
+    writer: W,
+    staging: &Path,
+    label: Option<&str>,
+) -> GenericResult<(W, Manifest)> {
+    let files_tar = TempFile(staging.with_extension("files"));
+    let mut files_builder = Builder::new(BufWriter::new(fs::File::create(&files_tar.0)?));
+    let mut files = vec![];
+    for (file_path, relative_path) in source_files(&config.sources) {
+        let file = fs::File::open(&file_path)?;
+        let mut header = Header::new_gnu();
+        header.set_metadata(&file.metadata()?);
+
+        // The header already holds the size, so ignore anything appended while reading
+        let mut reader = HashingReader::new(file.take(header.size()?));
+        files_builder.append_data(&mut header, &relative_path, &mut reader)?;
+        let (size, hash) = reader.finish();
+        files.push(FileRecord::new(&relative_path, &file_path, size, hash)?);
+    }
+    files_builder.into_inner()?.flush()?;
+
+    let mut builder = Builder::new(writer);
+    let manifest = Manifest::new(Some(config), files, label);
+    let contents = serde_yaml::to_string(&manifest)?;
+    let mut header = Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(time_now().try_into()?);
+    builder.append_data(&mut header, MANIFEST_FILE, contents.as_bytes())?;
+
+    let mut files_archive = Archive::new(BufReader::new(fs::File::open(&files_tar.0)?));
+    for entry in files_archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut header = entry.header().clone();
+        builder.append_data(&mut header, path, &mut entry)?;
+    }
+    Ok((builder.into_inner()?, manifest))
+}
+
+/// Opens a tar backup, picking the decompressor from its extension. Encrypted
+/// backups are decrypted as they are read.
+pub fn open_tar(backup: &BackupEntry) -> GenericResult<Archive<Box<dyn Read>>> {
+    let file: Box<dyn Read> = if backup.is_encrypted() {
+        Box::new(DecryptingReader::open(&backup.path, &backup_key(backup)?)?)
+    } else {
+        Box::new(fs::File::open(&backup.path)?)
+    };
+    let reader: Box<dyn Read> = if backup
+        .archive_name()
+        .ends_with(BackupFormat::TarZst.extension())
+    {
+        Box::new(zstd::Decoder::new(file)?)
+    } else {
+        Box::new(GzDecoder::new(file))
+    };
+    Ok(Archive::new(reader))
+}
+
+pub fn read_tar_manifest(backup: &BackupEntry) -> GenericResult<Option<Manifest>> {
+    let mut archive = open_tar(backup)?;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.path()? == Path::new(MANIFEST_FILE) {
+            return Ok(Some(serde_yaml::from_reader(entry)?));
+        }
+    }
+    Ok(None)
+}
+
+pub fn restore_tar_backup(
+    sources: &[Source],
+    backup: &BackupEntry,
+) -> GenericResult<HashSet<PathBuf>> {
+    let mut archive = open_tar(backup)?;
+    let mut restored = HashSet::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let relative_path = entry.path()?.into_owned();
+        if !relative_path
+            .components()
+            .all(|x| matches!(x, Component::Normal(_)))
+        {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "Invalid file name in backup").into(),
+            );
+        }
+        if relative_path == Path::new(MANIFEST_FILE) {
+            continue;
+        }
+        let target = match source_target(sources, &relative_path) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        fs::create_dir_all(target.parent().unwrap())?;
+        let mut output = fs::File::create(&target)?;
+        io::copy(&mut entry, &mut output)?;
+        restored.insert(relative_path);
+    }
+    Ok(restored)
+}
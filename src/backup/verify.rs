@@ -0,0 +1,337 @@
+use std::{collections::HashMap, fmt, fs};
+
+use zip::ZipArchive;
+
+use crate::utils::utils::GenericResult;
+
+use super::{
+    backup_types::BackupEntry,
+    encrypt::ArchiveFile,
+    manifest::{hash_reader, Manifest, MANIFEST_FILE},
+    store::{object_dir, object_path},
+    tarball::open_tar,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// The backup has no manifest to check files against
+    NoManifest,
+    Missing(String),
+    Truncated(String),
+    Altered(String),
+    Unreadable(String, String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NoManifest => write!(f, "no manifest, file contents not checked"),
+            Problem::Missing(path) => write!(f, "{} is missing", path),
+            Problem::Truncated(path) => write!(f, "{} is truncated", path),
+            Problem::Altered(path) => write!(f, "{} has been altered", path),
+            Problem::Unreadable(path, error) => write!(f, "{} cannot be read: {}", path, error),
+        }
+    }
+}
+
+/// Size and hash of each file found in a backup
+type FoundFiles = HashMap<String, (u64, String)>;
+
+/// Checks every file in a backup against its manifest
+pub fn verify_backup(backup: &BackupEntry) -> GenericResult<Vec<Problem>> {
+    let manifest = match Manifest::from_backup(backup) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            return Ok(vec![Problem::Unreadable(
+                MANIFEST_FILE.to_owned(),
+                error.to_string(),
+            )])
+        }
+    };
+    let mut problems = vec![];
+    let found = if backup.is_zip() {
+        read_zip_backup(backup, &mut problems)?
+    } else if backup.is_tar() {
+        read_tar_backup(backup, &mut problems)?
+    } else if let Some(manifest) = &manifest {
+        read_listed_files(backup, manifest, &mut problems)
+    } else {
+        FoundFiles::new()
+    };
+
+    match manifest {
+        Some(manifest) => {
+            for file in manifest.files {
+                match found.get(&file.path) {
+                    None => {
+                        if !problems.iter().any(
+                            |x| matches!(x, Problem::Unreadable(path, _) if path == &file.path),
+                        ) {
+                            problems.push(Problem::Missing(file.path));
+                        }
+                    }
+                    Some((size, _)) if *size < file.size => {
+                        problems.push(Problem::Truncated(file.path))
+                    }
+                    Some((size, hash)) if *size != file.size || hash != &file.hash => {
+                        problems.push(Problem::Altered(file.path))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        None => problems.push(Problem::NoManifest),
+    }
+    Ok(problems)
+}
+
+/// Reads every entry of a zip so corrupted entries fail their CRC check
+fn read_zip_backup(backup: &BackupEntry, problems: &mut Vec<Problem>) -> GenericResult<FoundFiles> {
+    let mut found = FoundFiles::new();
+    let mut archive = match ZipArchive::new(ArchiveFile::open(backup)?) {
+        Ok(archive) => archive,
+        Err(error) => {
+            problems.push(Problem::Unreadable(
+                backup.path.to_string_lossy().into_owned(),
+                error.to_string(),
+            ));
+            return Ok(found);
+        }
+    };
+    for i in 0..archive.len() {
+        let mut file = match archive.by_index(i) {
+            Ok(file) => file,
+            Err(error) => {
+                problems.push(Problem::Unreadable(
+                    format!("entry {}", i),
+                    error.to_string(),
+                ));
+                continue;
+            }
+        };
+        let name = file.name().replace('\\', "/");
+        if file.is_dir() || name == MANIFEST_FILE {
+            continue;
+        }
+        match hash_reader(&mut file) {
+            Ok(result) => {
+                found.insert(name, result);
+            }
+            Err(error) => problems.push(Problem::Unreadable(name, error.to_string())),
+        }
+    }
+    Ok(found)
+}
+
+/// Reads every entry of a tar archive, stopping at the first unreadable entry
+/// since the rest of the compressed stream cannot be trusted
+fn read_tar_backup(backup: &BackupEntry, problems: &mut Vec<Problem>) -> GenericResult<FoundFiles> {
+    let mut found = FoundFiles::new();
+    let mut archive = open_tar(backup)?;
+    let entries = match archive.entries() {
+        Ok(entries) => entries,
+        Err(error) => {
+            problems.push(Problem::Unreadable(
+                backup.path.to_string_lossy().into_owned(),
+                error.to_string(),
+            ));
+            return Ok(found);
+        }
+    };
+    for (i, entry) in entries.enumerate() {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                problems.push(Problem::Unreadable(
+                    format!("entry {}", i),
+                    error.to_string(),
+                ));
+                break;
+            }
+        };
+        let name = entry.path()?.to_string_lossy().replace('\\', "/");
+        if !entry.header().entry_type().is_file() || name == MANIFEST_FILE {
+            continue;
+        }
+        match hash_reader(&mut entry) {
+            Ok(result) => {
+                found.insert(name, result);
+            }
+            Err(error) => {
+                problems.push(Problem::Unreadable(name, error.to_string()));
+                break;
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Reads the files a manifest lists from a folder or the object store
+fn read_listed_files(
+    backup: &BackupEntry,
+    manifest: &Manifest,
+    problems: &mut Vec<Problem>,
+) -> FoundFiles {
+    let mut found = FoundFiles::new();
+    for file in &manifest.files {
+        let path = if backup.is_store() {
+            match object_path(&object_dir(backup.path.parent().unwrap()), &file.hash) {
+                Ok(path) => path,
+                Err(error) => {
+                    problems.push(Problem::Unreadable(file.path.clone(), error.to_string()));
+                    continue;
+                }
+            }
+        } else {
+            backup.path.join(&file.path)
+        };
+        if !path.exists() {
+            continue;
+        }
+        match fs::File::open(&path).and_then(|mut x| hash_reader(&mut x)) {
+            Ok(result) => {
+                found.insert(file.path.clone(), result);
+            }
+            Err(error) => problems.push(Problem::Unreadable(file.path.clone(), error.to_string())),
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Read, Seek, SeekFrom, Write},
+    };
+
+    use crate::{
+        backup::{backup_types::BackupKind, file_data::get_backups, run::create_backup},
+        config::config_types::{BackupFormat, Compression, GameConfig, KeySource},
+        utils::{path::test_dir, utils::GenericResult},
+    };
+
+    use super::{verify_backup, Problem};
+
+    fn verify_config(
+        format: BackupFormat,
+        dedup: bool,
+        encrypt: bool,
+    ) -> GenericResult<GameConfig> {
+        let base = test_dir(&format!("verify/{}-{}-{}", format.name(), dedup, encrypt));
+        fs::create_dir_all(base.join("src"))?;
+        fs::write(base.join("key"), "passphrase")?;
+        fs::write(base.join("src/file.txt"), "some file contents")?;
+        fs::write(base.join("src/other.txt"), "other file contents")?;
+        let config = GameConfig {
+            format,
+            encrypt: encrypt.then(|| KeySource::File(base.join("key"))),
+            dedup,
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst/thing"))
+        };
+        create_backup(&config, BackupKind::Regular)?;
+        Ok(config)
+    }
+
+    #[test]
+    fn test_verify_folder() -> GenericResult<()> {
+        let config = verify_config(BackupFormat::Folder, false, false)?;
+        let backup = &get_backups(&config)?[0];
+        assert_eq!(verify_backup(backup)?, vec![]);
+
+        fs::write(backup.path.join("file.txt"), "some file")?;
+        fs::remove_file(backup.path.join("other.txt"))?;
+        assert_eq!(
+            verify_backup(backup)?,
+            vec![
+                Problem::Truncated("file.txt".to_owned()),
+                Problem::Missing("other.txt".to_owned())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_store() -> GenericResult<()> {
+        let config = verify_config(BackupFormat::Folder, true, false)?;
+        let backup = &get_backups(&config)?[0];
+        assert_eq!(verify_backup(backup)?, vec![]);
+
+        let objects = config.save_dir.parent().unwrap().join(".objects");
+        for prefix in fs::read_dir(objects)? {
+            let prefix = prefix?.path();
+            if !prefix.is_dir() {
+                continue;
+            }
+            for object in fs::read_dir(prefix)? {
+                fs::write(object?.path(), "some file CONTENTS")?;
+            }
+        }
+        assert_eq!(
+            verify_backup(backup)?,
+            vec![
+                Problem::Altered("file.txt".to_owned()),
+                Problem::Truncated("other.txt".to_owned())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_zip() -> GenericResult<()> {
+        let config = verify_config(BackupFormat::Zip(Compression::Stored), false, false)?;
+        let backup = &get_backups(&config)?[0];
+        assert_eq!(verify_backup(backup)?, vec![]);
+
+        // Flip a byte of the first stored file's data
+        let mut zip = fs::File::options()
+            .read(true)
+            .write(true)
+            .open(&backup.path)?;
+        let mut contents = vec![];
+        zip.read_to_end(&mut contents)?;
+        let name_end = contents.windows(8).position(|x| x == b"file.txt").unwrap() + 8;
+        zip.seek(SeekFrom::Start(name_end as u64))?;
+        zip.write_all(&[contents[name_end] ^ 0xff])?;
+
+        let problems = verify_backup(backup)?;
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(&problems[0], Problem::Unreadable(path, _) if path == "file.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_tar() -> GenericResult<()> {
+        let config = verify_config(BackupFormat::TarGz, false, false)?;
+        let backup = &get_backups(&config)?[0];
+        assert_eq!(verify_backup(backup)?, vec![]);
+
+        // Cut the archive off partway through the compressed stream
+        let contents = fs::read(&backup.path)?;
+        fs::write(&backup.path, &contents[..contents.len() / 2])?;
+        assert!(!verify_backup(backup)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_encrypted() -> GenericResult<()> {
+        let mut config = verify_config(BackupFormat::TarGz, false, true)?;
+        let backup = &get_backups(&config)?[0];
+        assert!(backup.is_encrypted());
+        assert_eq!(verify_backup(backup)?, vec![]);
+
+        let mut contents = fs::read(&backup.path)?;
+        let middle = contents.len() / 2;
+        contents[middle] ^= 0xff;
+        fs::write(&backup.path, &contents)?;
+        assert!(matches!(
+            &verify_backup(backup)?[..],
+            [Problem::Unreadable(..)]
+        ));
+
+        // Without the key the backup cannot be read at all
+        config.encrypt = None;
+        assert!(verify_backup(&get_backups(&config)?[0]).is_ok_and(|x| !x.is_empty()));
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{config::config_types::GameConfig, utils::utils::GenericResult};
+
+use super::run::run_backup;
+
+pub fn start_watch_loop(config: &GameConfig) -> GenericResult<()> {
+    let watcher = FileWatcher::new(config)?;
+    let debounce = Duration::from_secs(config.debounce);
+
+    // Catch up on changes made while not running
+    run_backup(config)?;
+    loop {
+        watcher.wait_for_change(config, None)?;
+
+        // Wait for the game to finish writing
+        while watcher.wait_for_change(config, Some(debounce))? {}
+        run_backup(config)?;
+    }
+}
+
+/// Watches the root of each source for changes, stopping when dropped
+struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<Event>>,
+    /// Events report canonical paths rather than paths relative to `root`,
+    /// so these are in the same order as the sources
+    canonical_roots: Vec<PathBuf>,
+}
+
+impl FileWatcher {
+    fn new(config: &GameConfig) -> GenericResult<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        let mut canonical_roots = vec![];
+        for source in &config.sources {
+            watcher.watch(&source.file_list.root, RecursiveMode::Recursive)?;
+            canonical_roots.push(fs::canonicalize(&source.file_list.root)?);
+        }
+        Ok(Self {
+            _watcher: watcher,
+            receiver,
+            canonical_roots,
+        })
+    }
+
+    /// Blocks until a file in the list changes, returning false if `timeout` passes first
+    fn wait_for_change(
+        &self,
+        config: &GameConfig,
+        timeout: Option<Duration>,
+    ) -> GenericResult<bool> {
+        let deadline = timeout.map(|x| Instant::now() + x);
+        loop {
+            let event = match deadline {
+                Some(deadline) => match self
+                    .receiver
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => return Ok(false),
+                    Err(error) => return Err(error.into()),
+                },
+                None => self.receiver.recv()?,
+            }?;
+            if !event.kind.is_access() && event.paths.iter().any(|x| self.is_listed(config, x)) {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn is_listed(&self, config: &GameConfig, path: &Path) -> bool {
+        config
+            .sources
+            .iter()
+            .zip(&self.canonical_roots)
+            .any(
+                |(source, canonical_root)| match path.strip_prefix(canonical_root) {
+                    Ok(relative_path) => source
+                        .file_list
+                        .contains(&source.file_list.root.join(relative_path)),
+                    Err(_) => false,
+                },
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use crate::{
+        config::config_types::{FileList, GameConfig, Source, Trigger},
+        utils::{path::test_dir, utils::GenericResult},
+    };
+
+    use super::FileWatcher;
+
+    #[test]
+    fn test_watch() -> GenericResult<()> {
+        let base = test_dir("watch");
+        fs::create_dir_all(base.join("src"))?;
+        let config = GameConfig {
+            sources: vec![Source::new(
+                None,
+                FileList::new(
+                    base.join("src").to_str().unwrap(),
+                    None,
+                    Some(vec!["ignored.txt".to_owned()]),
+                ),
+            )],
+            trigger: Trigger::Watch,
+            debounce: 1,
+            ..GameConfig::test("thing", &base.join("src"), &base.join("dst"))
+        };
+        let timeout = Some(Duration::from_secs(1));
+        let watcher = FileWatcher::new(&config)?;
+
+        fs::write(base.join("src/ignored.txt"), "ignored")?;
+        assert!(!watcher.wait_for_change(&config, timeout)?);
+
+        fs::write(base.join("src/file.txt"), "changed")?;
+        assert!(watcher.wait_for_change(&config, timeout)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+use std::path::PathBuf;
+
+use crate::{
+    backup::{diff::DiffSide, restore::BackupSelector},
+    utils::utils::GenericResult,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run,
+    Restore {
+        name: String,
+        selector: BackupSelector,
+    },
+    /// Copies the files of a backup under `path`, or all of them, into `target`
+    Extract {
+        name: String,
+        selector: BackupSelector,
+        target: PathBuf,
+        path: Option<String>,
+    },
+    /// Backs up a game, or every game if `name` is `None`, outside the schedule
+    BackupNow {
+        name: Option<String>,
+        label: Option<String>,
+    },
+    /// Checks the config at `path`, or the one `--config` or the search finds
+    CheckConfig {
+        path: Option<PathBuf>,
+    },
+    Diff {
+        name: String,
+        from: DiffSide,
+        to: DiffSide,
+    },
+    List {
+        name: Option<String>,
+        json: bool,
+    },
+    Pin {
+        name: String,
+        selector: BackupSelector,
+    },
+    Unpin {
+        name: String,
+        selector: BackupSelector,
+    },
+    PruneSnapshots {
+        name: Option<String>,
+    },
+    Verify {
+        name: Option<String>,
+    },
+}
+
+/// Removes `--config <path>` from anywhere in the arguments, returning the path
+/// and the arguments left for the command
+pub fn split_config_arg<I: Iterator<Item = String>>(
+    mut args: I,
+) -> GenericResult<(Option<PathBuf>, Vec<String>)> {
+    let mut config = None;
+    let mut rest = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            let path = args.next().ok_or("Usage: --config <path>")?;
+            config = Some(PathBuf::from(path));
+        } else {
+            rest.push(arg);
+        }
+    }
+    Ok((config, rest))
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> GenericResult<Command> {
+    let command = match args.next() {
+        None => return Ok(Command::Run),
+        Some(command) => command,
+    };
+    match command.as_str() {
+        "restore" => {
+            let name = args
+                .next()
+                .ok_or("Usage: restore <name> [timestamp|latest|snapshot]")?;
+            let selector = BackupSelector::parse(args.next().as_deref());
+            Ok(Command::Restore { name, selector })
+        }
+        "extract" => {
+            let usage = "Usage: extract <name> <timestamp|latest|snapshot> <target> [path]";
+            let name = args.next().ok_or(usage)?;
+            let selector = BackupSelector::parse(Some(&args.next().ok_or(usage)?));
+            let target = PathBuf::from(args.next().ok_or(usage)?);
+            let path = args.next();
+            Ok(Command::Extract {
+                name,
+                selector,
+                target,
+                path,
+            })
+        }
+        "backup-now" => {
+            let usage = "Usage: backup-now <name|all> [--label <label>]";
+            let name = args.next().ok_or(usage)?;
+            let name = (name != "all").then_some(name);
+            let label = match args.next().as_deref() {
+                None => None,
+                Some("--label") => Some(args.next().ok_or(usage)?),
+                Some(_) => return Err(usage.into()),
+            };
+            Ok(Command::BackupNow { name, label })
+        }
+        "check-config" => Ok(Command::CheckConfig {
+            path: args.next().map(PathBuf::from),
+        }),
+        "diff" => {
+            let usage =
+                "Usage: diff <name> <timestamp|latest|snapshot> [timestamp|latest|snapshot|live]";
+            let name = args.next().ok_or(usage)?;
+            let from = DiffSide::Backup(BackupSelector::parse(Some(&args.next().ok_or(usage)?)));
+            let to = DiffSide::parse(args.next().as_deref());
+            Ok(Command::Diff { name, from, to })
+        }
+        "list" => {
+            let mut name = None;
+            let mut json = false;
+            for arg in args {
+                match arg.as_str() {
+                    "--json" => json = true,
+                    _ if name.is_none() => name = Some(arg),
+                    _ => return Err("Usage: list [name] [--json]".into()),
+                }
+            }
+            Ok(Command::List { name, json })
+        }
+        "pin" | "unpin" => {
+            let name = args
+                .next()
+                .ok_or_else(|| format!("Usage: {} <name> [timestamp|latest]", command))?;
+            let selector = BackupSelector::parse(args.next().as_deref());
+            if command == "pin" {
+                Ok(Command::Pin { name, selector })
+            } else {
+                Ok(Command::Unpin { name, selector })
+            }
+        }
+        "prune-snapshots" => Ok(Command::PruneSnapshots { name: args.next() }),
+        "verify" => Ok(Command::Verify { name: args.next() }),
+        _ => Err(format!("Unknown command {}", command).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::backup::{diff::DiffSide, restore::BackupSelector};
+
+    use super::{parse_args, split_config_arg, Command};
+
+    fn parse(args: &[&str]) -> Command {
+        parse_args(args.iter().map(|x| x.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse(&[]), Command::Run);
+        assert_eq!(
+            parse(&["restore", "thing"]),
+            Command::Restore {
+                name: "thing".to_owned(),
+                selector: BackupSelector::Latest
+            }
+        );
+        assert_eq!(
+            parse(&["restore", "thing", "2022-04-15_21-02-01+1000"]),
+            Command::Restore {
+                name: "thing".to_owned(),
+                selector: BackupSelector::Timestamp("2022-04-15_21-02-01+1000".to_owned())
+            }
+        );
+        assert!(parse_args(["restore".to_owned()].into_iter()).is_err());
+        assert_eq!(
+            parse(&["extract", "thing", "latest", "out", "sub/file.sav"]),
+            Command::Extract {
+                name: "thing".to_owned(),
+                selector: BackupSelector::Latest,
+                target: PathBuf::from("out"),
+                path: Some("sub/file.sav".to_owned())
+            }
+        );
+        assert!(parse_args(
+            ["extract", "thing", "latest"]
+                .map(|x| x.to_owned())
+                .into_iter()
+        )
+        .is_err());
+        assert_eq!(
+            parse(&["backup-now", "all"]),
+            Command::BackupNow {
+                name: None,
+                label: None
+            }
+        );
+        assert_eq!(
+            parse(&["backup-now", "thing", "--label", "before patch 1.3"]),
+            Command::BackupNow {
+                name: Some("thing".to_owned()),
+                label: Some("before patch 1.3".to_owned())
+            }
+        );
+        assert!(parse_args(
+            ["backup-now", "thing", "--label"]
+                .map(|x| x.to_owned())
+                .into_iter()
+        )
+        .is_err());
+        assert_eq!(
+            parse(&["check-config"]),
+            Command::CheckConfig { path: None }
+        );
+        assert_eq!(
+            parse(&["check-config", "other.yaml"]),
+            Command::CheckConfig {
+                path: Some(PathBuf::from("other.yaml"))
+            }
+        );
+        assert_eq!(
+            parse(&["diff", "thing", "latest"]),
+            Command::Diff {
+                name: "thing".to_owned(),
+                from: DiffSide::Backup(BackupSelector::Latest),
+                to: DiffSide::Live
+            }
+        );
+        assert_eq!(
+            parse(&["diff", "thing", "2022-04-15_21-02-01+1000", "snapshot"]),
+            Command::Diff {
+                name: "thing".to_owned(),
+                from: DiffSide::Backup(BackupSelector::Timestamp(
+                    "2022-04-15_21-02-01+1000".to_owned()
+                )),
+                to: DiffSide::Backup(BackupSelector::Snapshot)
+            }
+        );
+        assert!(parse_args(["diff", "thing"].map(|x| x.to_owned()).into_iter()).is_err());
+        assert_eq!(
+            parse(&["list", "--json"]),
+            Command::List {
+                name: None,
+                json: true
+            }
+        );
+        assert_eq!(
+            parse(&["list", "thing"]),
+            Command::List {
+                name: Some("thing".to_owned()),
+                json: false
+            }
+        );
+        assert_eq!(
+            parse(&["pin", "thing"]),
+            Command::Pin {
+                name: "thing".to_owned(),
+                selector: BackupSelector::Latest
+            }
+        );
+        assert_eq!(
+            parse(&["unpin", "thing", "2022-04-15_21-02-01+1000"]),
+            Command::Unpin {
+                name: "thing".to_owned(),
+                selector: BackupSelector::Timestamp("2022-04-15_21-02-01+1000".to_owned())
+            }
+        );
+        assert!(parse_args(["pin".to_owned()].into_iter()).is_err());
+        assert_eq!(
+            parse(&["prune-snapshots"]),
+            Command::PruneSnapshots { name: None }
+        );
+        assert_eq!(
+            parse(&["verify", "thing"]),
+            Command::Verify {
+                name: Some("thing".to_owned())
+            }
+        );
+    }
+
+    #[test]
+    fn test_split_config_arg() {
+        let split = |args: &[&str]| split_config_arg(args.iter().map(|x| x.to_string()));
+        assert_eq!(split(&[]).unwrap(), (None, vec![]));
+        assert_eq!(
+            split(&["--config", "/etc/save-backup.yaml", "list", "thing"]).unwrap(),
+            (
+                Some(PathBuf::from("/etc/save-backup.yaml")),
+                vec!["list".to_owned(), "thing".to_owned()]
+            )
+        );
+        assert_eq!(
+            split(&["verify", "--config", "other.yaml"]).unwrap(),
+            (Some(PathBuf::from("other.yaml")), vec!["verify".to_owned()])
+        );
+        assert!(split(&["list", "--config"]).is_err());
+    }
+}
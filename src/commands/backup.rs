@@ -0,0 +1,25 @@
+use crate::{
+    backup::run::backup_now,
+    config::{config_types::GameConfig, parse::find_config},
+    utils::utils::GenericResult,
+};
+
+/// Backs up one game, or every game if no name is given, outside the schedule
+pub fn run_backup_now(
+    configs: Vec<GameConfig>,
+    name: Option<&str>,
+    label: Option<&str>,
+) -> GenericResult<()> {
+    let configs = match name {
+        Some(name) => vec![find_config(configs, name)?],
+        None => configs,
+    };
+    for config in configs {
+        backup_now(&config, label)?;
+        match label {
+            Some(label) => println!("Backed up {} ({})", &config.name, label),
+            None => println!("Backed up {}", &config.name),
+        }
+    }
+    Ok(())
+}
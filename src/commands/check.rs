@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use crate::{
+    config::{check::check_config, parse::load_config},
+    utils::utils::GenericResult,
+};
+
+/// Reports problems with a config file without starting any backups, failing if there are any
+pub fn run_check_config(path: &Path) -> GenericResult<()> {
+    let configs = load_config(path)?;
+    let mut problems = 0;
+    for config in &configs {
+        let found = check_config(config, &configs);
+        if found.is_empty() {
+            println!("{}: OK", &config.name);
+        }
+        for problem in &found {
+            println!("{}: {}", &config.name, problem);
+        }
+        problems += found.len();
+    }
+    if problems > 0 {
+        return Err(format!("{} problems found in {}", problems, path.display()).into());
+    }
+    println!("{} is valid", path.display());
+    Ok(())
+}
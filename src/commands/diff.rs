@@ -0,0 +1,36 @@
+use crate::{
+    backup::diff::{diff_backups, DiffSide},
+    config::{config_types::GameConfig, parse::find_config},
+    utils::utils::GenericResult,
+};
+
+/// Prints the files that differ between two backups, or a backup and the live files
+pub fn run_diff(
+    configs: Vec<GameConfig>,
+    name: &str,
+    from: &DiffSide,
+    to: &DiffSide,
+) -> GenericResult<()> {
+    let config = find_config(configs, name)?;
+    let diff = diff_backups(&config, from, to)?;
+    if diff.is_empty() {
+        println!("No differences");
+        return Ok(());
+    }
+    for path in &diff.added {
+        println!("+ {}", path);
+    }
+    for path in &diff.removed {
+        println!("- {}", path);
+    }
+    for path in &diff.modified {
+        println!("M {}", path);
+    }
+    println!(
+        "{} added, {} removed, {} modified",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use crate::{
+    backup::list::{list_backup_info, BackupInfo},
+    config::{config_types::GameConfig, parse::find_config},
+    utils::utils::{format_size, GenericResult},
+};
+
+/// Prints the backups of one game, or every game if no name is given
+pub fn run_list(configs: Vec<GameConfig>, name: Option<&str>, json: bool) -> GenericResult<()> {
+    let configs = match name {
+        Some(name) => vec![find_config(configs, name)?],
+        None => configs,
+    };
+    let mut info: Vec<BackupInfo> = vec![];
+    for config in &configs {
+        info.extend(list_backup_info(config)?);
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    for config in &configs {
+        println!("{}", &config.name);
+        for backup in info.iter().filter(|x| x.game == config.name) {
+            let files = match backup.files {
+                Some(files) => format!("{} files", files),
+                None => "? files".to_owned(),
+            };
+            let mut flags = vec![];
+            if backup.encrypted {
+                flags.push("encrypted");
+            }
+            if backup.snapshot {
+                flags.push("snapshot");
+            }
+            if backup.pinned {
+                flags.push("pinned");
+            }
+            println!(
+                "  {}  {}  {:>10}  {:>9}  {:<7}  {}  {}",
+                backup.local_time(),
+                &backup.timestamp,
+                format_size(backup.size),
+                files,
+                &backup.format,
+                flags.join(","),
+                backup.label.as_deref().unwrap_or("")
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use crate::{
+    backup::{pin::set_pinned, restore::BackupSelector},
+    config::{config_types::GameConfig, parse::find_config},
+    utils::utils::GenericResult,
+};
+
+/// Pins or unpins a backup so rotation keeps or may remove it
+pub fn run_pin(
+    configs: Vec<GameConfig>,
+    name: &str,
+    selector: &BackupSelector,
+    pinned: bool,
+) -> GenericResult<()> {
+    let config = find_config(configs, name)?;
+    let backup = set_pinned(&config, selector, pinned)?;
+    println!(
+        "{} {} {}",
+        if pinned { "Pinned" } else { "Unpinned" },
+        &config.name,
+        &backup.timestamp
+    );
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use crate::{
+    backup::{
+        restore::{extract_backup, restore_backup, BackupSelector},
+        run::remove_snapshots,
+    },
+    config::{config_types::GameConfig, parse::find_config},
+    utils::utils::GenericResult,
+};
+
+pub fn run_restore(
+    configs: Vec<GameConfig>,
+    name: &str,
+    selector: &BackupSelector,
+) -> GenericResult<()> {
+    let config = find_config(configs, name)?;
+    restore_backup(&config, selector)?;
+    println!("Restored {}", &config.name);
+    Ok(())
+}
+
+/// Copies files from a backup into a directory other than the game's root
+pub fn run_extract(
+    configs: Vec<GameConfig>,
+    name: &str,
+    selector: &BackupSelector,
+    target: &Path,
+    path: Option<&str>,
+) -> GenericResult<()> {
+    let config = find_config(configs, name)?;
+    let count = extract_backup(&config, selector, target, path)?;
+    println!("Extracted {} files to {}", count, target.to_string_lossy());
+    Ok(())
+}
+
+/// Removes restore snapshots for one game, or every game if no name is given
+pub fn run_prune_snapshots(configs: Vec<GameConfig>, name: Option<&str>) -> GenericResult<()> {
+    let configs = match name {
+        Some(name) => vec![find_config(configs, name)?],
+        None => configs,
+    };
+    for config in configs {
+        let count = remove_snapshots(&config)?;
+        println!("Removed {} snapshots for {}", count, &config.name);
+    }
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use crate::{
+    backup::{
+        file_data::get_backups,
+        verify::{verify_backup, Problem},
+    },
+    config::{config_types::GameConfig, parse::find_config},
+    utils::utils::GenericResult,
+};
+
+/// Checks the backups of one game, or every game if no name is given, and fails if any are damaged
+pub fn run_verify(configs: Vec<GameConfig>, name: Option<&str>) -> GenericResult<()> {
+    let configs = match name {
+        Some(name) => vec![find_config(configs, name)?],
+        None => configs,
+    };
+    let mut damaged = 0;
+    for config in configs {
+        for backup in get_backups(&config)? {
+            let problems = verify_backup(&backup)?;
+            if problems.is_empty() {
+                println!("{} {}: OK", &config.name, &backup.timestamp);
+                continue;
+            }
+            for problem in &problems {
+                println!("{} {}: {}", &config.name, &backup.timestamp, problem);
+            }
+            // Old backups without a manifest can't be checked but aren't known to be damaged
+            if problems.iter().any(|x| x != &Problem::NoManifest) {
+                damaged += 1;
+            }
+        }
+    }
+    if damaged > 0 {
+        return Err(format!("{} damaged backups found", damaged).into());
+    }
+    Ok(())
+}
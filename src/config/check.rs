@@ -0,0 +1,169 @@
+use std::{
+    fmt, fs,
+    path::{self, Path, PathBuf},
+};
+
+use crate::utils::path::temp_path;
+
+use super::config_types::{FileList, GameConfig};
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigProblem {
+    MissingRoot(PathBuf),
+    /// An include pattern matches no files
+    MissingInclude(String),
+    NotWritable(PathBuf, String),
+    /// Backups would be written inside a root of the named game
+    InsideRoot(PathBuf, String),
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::MissingRoot(path) => write!(f, "root {:?} does not exist", path),
+            ConfigProblem::MissingInclude(pattern) => {
+                write!(f, "include pattern {:?} matches no files", pattern)
+            }
+            ConfigProblem::NotWritable(path, error) => {
+                write!(f, "save_root {:?} is not writable: {}", path, error)
+            }
+            ConfigProblem::InsideRoot(path, name) => {
+                write!(f, "backups in {:?} overlap the root of {}", path, name)
+            }
+        }
+    }
+}
+
+/// Checks the paths of a game against the file system and the roots of every game
+pub fn check_config(config: &GameConfig, configs: &[GameConfig]) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+    for source in &config.sources {
+        let root = &source.file_list.root;
+        if !root.is_dir() {
+            problems.push(ConfigProblem::MissingRoot(root.clone()));
+        } else if let Some(include) = source.file_list.include() {
+            for pattern in include.iter().filter(|x| !x.starts_with('!')) {
+                let file_list =
+                    FileList::new(root.to_str().unwrap(), Some(vec![pattern.clone()]), None);
+                if file_list.into_iter().next().is_none() {
+                    problems.push(ConfigProblem::MissingInclude(pattern.clone()));
+                }
+            }
+        }
+    }
+
+    let save_root = config.save_dir.parent().unwrap_or(&config.save_dir);
+    if let Err(error) = check_writable(save_root) {
+        problems.push(ConfigProblem::NotWritable(
+            save_root.to_owned(),
+            error.to_string(),
+        ));
+    }
+
+    let save_dir = absolute(&config.save_dir);
+    for other in configs {
+        if other.sources.iter().any(|x| {
+            let other_root = absolute(&x.file_list.root);
+            save_dir.starts_with(&other_root) || other_root.starts_with(&save_dir)
+        }) {
+            problems.push(ConfigProblem::InsideRoot(
+                config.save_dir.clone(),
+                other.name.clone(),
+            ));
+        }
+    }
+    problems
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    let path = path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    // Drop `.` components so equal paths compare equal
+    path.components().collect()
+}
+
+/// Writes and removes a scratch file in `path`, or the closest ancestor that
+/// exists, without creating any directories
+fn check_writable(path: &Path) -> std::io::Result<()> {
+    let existing = path
+        .ancestors()
+        .find(|x| x.as_os_str().is_empty() || x.exists())
+        .unwrap_or(path);
+    let existing = if existing.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        existing
+    };
+    let scratch = temp_path(&existing.join("check-config"));
+    fs::write(&scratch, "")?;
+    fs::remove_file(&scratch)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        slice,
+    };
+
+    use crate::{
+        config::config_types::{BackupFormat, FileList, GameConfig, Source},
+        utils::path::test_dir,
+    };
+
+    use super::{check_config, ConfigProblem};
+
+    fn config(name: &str, root: &Path, save_dir: PathBuf, include: Option<&str>) -> GameConfig {
+        GameConfig {
+            sources: vec![Source::new(
+                None,
+                FileList::new(
+                    root.to_str().unwrap(),
+                    include.map(|x| vec![x.to_owned()]),
+                    None,
+                ),
+            )],
+            format: BackupFormat::Folder,
+            ..GameConfig::test(name, root, &save_dir)
+        }
+    }
+
+    #[test]
+    fn test_check_config() {
+        let base = test_dir("check");
+        fs::create_dir_all(base.join("src/saves")).unwrap();
+        fs::write(base.join("src/saves/save.dat"), "").unwrap();
+        let root = base.join("src");
+
+        let good = config("good", &root, base.join("dst/good"), Some("saves"));
+        assert_eq!(check_config(&good, slice::from_ref(&good)), vec![]);
+        // Nothing is created while checking that save_root is writable
+        assert!(!base.join("dst").exists());
+
+        let missing = config(
+            "missing",
+            &base.join("nowhere"),
+            base.join("dst/missing"),
+            None,
+        );
+        assert_eq!(
+            check_config(&missing, slice::from_ref(&missing)),
+            vec![ConfigProblem::MissingRoot(base.join("nowhere"))]
+        );
+
+        let include = config("include", &root, base.join("dst/include"), Some("gone"));
+        assert_eq!(
+            check_config(&include, slice::from_ref(&include)),
+            vec![ConfigProblem::MissingInclude("gone".to_owned())]
+        );
+
+        let inside = config("inside", &root, root.join("./backups/inside"), None);
+        assert_eq!(
+            check_config(&inside, &[good.clone(), inside.clone()]),
+            vec![
+                ConfigProblem::InsideRoot(root.join("./backups/inside"), "good".to_owned()),
+                ConfigProblem::InsideRoot(root.join("./backups/inside"), "inside".to_owned())
+            ]
+        );
+    }
+}
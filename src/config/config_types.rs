@@ -1,17 +1,153 @@
 use core::fmt;
 use std::{
-    fs::{self, ReadDir},
-    path::PathBuf,
+    fs,
+    path::{Path, PathBuf},
 };
 
-use crate::utils::log::LogExpectResult;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    log::LogExpectResult,
+    path::{data_dir, resolve_path},
+    utils::GenericResult,
+};
+
+/// What causes a game to be checked for changes
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    /// Check every `interval` minutes
+    Interval,
+    /// Check once files under `root` stop changing for `debounce` seconds
+    Watch,
+}
+
+/// How zip backups compress each file
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Compression {
+    Stored,
+    Deflate,
+    Bzip2,
+    Zstd,
+}
+
+/// How the files of a backup are written to `save_dir`
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BackupFormat {
+    /// Plain copy of the files
+    Folder,
+    Zip(Compression),
+    TarGz,
+    TarZst,
+}
+
+impl BackupFormat {
+    pub fn parse(format: &str) -> Option<BackupFormat> {
+        match format {
+            "folder" => Some(BackupFormat::Folder),
+            "zip" | "zip-deflate" => Some(BackupFormat::Zip(Compression::Deflate)),
+            "zip-stored" => Some(BackupFormat::Zip(Compression::Stored)),
+            "zip-bzip2" => Some(BackupFormat::Zip(Compression::Bzip2)),
+            "zip-zstd" => Some(BackupFormat::Zip(Compression::Zstd)),
+            "tar.gz" => Some(BackupFormat::TarGz),
+            "tar.zst" => Some(BackupFormat::TarZst),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackupFormat::Folder => "folder",
+            BackupFormat::Zip(Compression::Stored) => "zip-stored",
+            BackupFormat::Zip(Compression::Deflate) => "zip-deflate",
+            BackupFormat::Zip(Compression::Bzip2) => "zip-bzip2",
+            BackupFormat::Zip(Compression::Zstd) => "zip-zstd",
+            BackupFormat::TarGz => "tar.gz",
+            BackupFormat::TarZst => "tar.zst",
+        }
+    }
+
+    /// Extension added to the backup name, including the leading dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BackupFormat::Folder => "",
+            BackupFormat::Zip(_) => ".zip",
+            BackupFormat::TarGz => ".tar.gz",
+            BackupFormat::TarZst => ".tar.zst",
+        }
+    }
+
+    /// Whether `level` is a compression level this format accepts
+    pub fn supports_level(&self, level: i32) -> bool {
+        match self {
+            BackupFormat::Folder | BackupFormat::Zip(Compression::Stored) => false,
+            BackupFormat::Zip(Compression::Deflate) | BackupFormat::TarGz => {
+                (0..=9).contains(&level)
+            }
+            BackupFormat::Zip(Compression::Bzip2) => (1..=9).contains(&level),
+            BackupFormat::Zip(Compression::Zstd) | BackupFormat::TarZst => {
+                (-7..=22).contains(&level)
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for BackupFormat {
+    type Error = String;
+
+    fn try_from(format: String) -> Result<Self, Self::Error> {
+        BackupFormat::parse(&format).ok_or_else(|| format!("Invalid format {}", format))
+    }
+}
+
+impl From<BackupFormat> for String {
+    fn from(format: BackupFormat) -> Self {
+        format.name().to_owned()
+    }
+}
+
+/// Where the passphrase of encrypted backups is read from
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum KeySource {
+    /// File containing the passphrase
+    #[serde(rename = "key_file")]
+    File(PathBuf),
+    /// Environment variable containing the passphrase
+    #[serde(rename = "key_env")]
+    Env(String),
+}
+
+/// How many backups to keep from each period, newest first
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retention {
+    /// Most recent backups
+    pub last: u64,
+    pub hourly: u64,
+    pub daily: u64,
+    pub weekly: u64,
+    pub monthly: u64,
+}
 
 #[derive(Debug, PartialEq)]
 pub struct SharedConfig {
     pub save_root: PathBuf,
-    pub zip: bool,
+    pub format: BackupFormat,
+    pub level: Option<i32>,
+    pub encrypt: Option<KeySource>,
+    pub dedup: bool,
     pub count: u64,
+    pub keep: Option<Retention>,
+    /// Limit in bytes on all backups under `save_root` together
+    pub max_total_size: Option<u64>,
+    pub max_age: Option<i64>,
     pub interval: i64,
+    pub trigger: Trigger,
+    pub debounce: u64,
+    /// Directory of the config file, which relative paths in it are read from
+    pub config_dir: PathBuf,
 }
 
 impl fmt::Display for SharedConfig {
@@ -20,30 +156,91 @@ impl fmt::Display for SharedConfig {
     }
 }
 
+/// Settings given at the top level of the config file or for one game, before
+/// defaults are filled in
+pub struct Settings {
+    pub save_root: Option<String>,
+    pub format: Option<BackupFormat>,
+    pub level: Option<i32>,
+    pub encrypt: Option<KeySource>,
+    pub dedup: Option<bool>,
+    pub count: Option<u64>,
+    pub keep: Option<Retention>,
+    pub max_total_size: Option<u64>,
+    pub max_age: Option<i64>,
+    pub interval: Option<i64>,
+    pub trigger: Option<Trigger>,
+    pub debounce: Option<u64>,
+}
+
 impl SharedConfig {
-    pub fn new(
-        save_root: Option<&str>,
-        zip: Option<bool>,
-        count: Option<u64>,
-        interval: Option<i64>,
-    ) -> SharedConfig {
-        SharedConfig {
-            save_root: PathBuf::from(save_root.unwrap_or("./save-backups")),
-            zip: zip.unwrap_or(true),
-            count: count.unwrap_or(5),
-            interval: interval.unwrap_or(30),
-        }
+    pub fn new(settings: Settings, config_dir: &Path) -> GenericResult<SharedConfig> {
+        // Without a save_root, backups go next to the log rather than wherever the
+        // application happens to be started from
+        let save_root = match settings.save_root {
+            Some(save_root) => {
+                resolve_path(config_dir, &save_root).map_err(|x| format!("save_root: {}", x))?
+            }
+            None => data_dir().join("save-backups"),
+        };
+        Ok(SharedConfig {
+            save_root,
+            format: settings
+                .format
+                .unwrap_or(BackupFormat::Zip(Compression::Deflate)),
+            level: settings.level,
+            encrypt: settings.encrypt,
+            dedup: settings.dedup.unwrap_or(false),
+            count: settings.count.unwrap_or(5),
+            keep: settings.keep,
+            max_total_size: settings.max_total_size,
+            max_age: settings.max_age,
+            interval: settings.interval.unwrap_or(30),
+            trigger: settings.trigger.unwrap_or(Trigger::Interval),
+            debounce: settings.debounce.unwrap_or(10),
+            config_dir: config_dir.to_owned(),
+        })
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Limit in bytes on the backups of every game under the top level `save_root`
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RootLimit {
+    pub save_root: PathBuf,
+    pub max_total_size: u64,
+    /// Configured games saving their backups under `save_root`
+    #[serde(default)]
+    pub games: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct GameConfig {
     pub name: String,
     pub save_dir: PathBuf,
-    pub zip: bool,
-    pub file_list: FileList,
+    pub format: BackupFormat,
+    /// Compression level, `None` for the format's default
+    pub level: Option<i32>,
+    /// Encrypt archives with the passphrase read from here
+    pub encrypt: Option<KeySource>,
+    /// Store file contents once by hash in a shared object directory
+    pub dedup: bool,
+    /// Folders backed up together
+    pub sources: Vec<Source>,
     pub interval: i64,
     pub count: u64,
+    /// Retention policy used instead of keeping the latest `count` backups
+    pub keep: Option<Retention>,
+    /// Limit in bytes on the backups of this game
+    pub max_total_size: Option<u64>,
+    /// Age in seconds after which backups are removed
+    pub max_age: Option<i64>,
+    /// Limit on all backups under the top level `save_root`
+    pub save_root_limit: Option<RootLimit>,
+    pub trigger: Trigger,
+    pub debounce: u64,
+    /// Executable name to back up after it exits
+    pub process: Option<String>,
+    pub backup_on_start: bool,
 }
 
 impl fmt::Display for GameConfig {
@@ -53,57 +250,368 @@ impl fmt::Display for GameConfig {
 }
 
 impl GameConfig {
+    /// Retention policy, which is the latest `count` backups unless `keep` is set
+    pub fn retention(&self) -> Retention {
+        self.keep.unwrap_or(Retention {
+            last: self.count,
+            ..Default::default()
+        })
+    }
+
     pub fn with_defaults(
         name: &str,
-        save_dir: Option<&str>,
-        zip: Option<bool>,
-        root: &str,
-        include: Option<Vec<String>>,
-        exclude: Option<Vec<String>>,
-        interval: Option<i64>,
-        count: Option<u64>,
+        settings: Settings,
+        sources: Vec<SourceConfig>,
+        process: Option<&str>,
+        backup_on_start: Option<bool>,
         defaults: &SharedConfig,
-    ) -> GameConfig {
-        let mut save_dir = PathBuf::from(save_dir.unwrap_or(defaults.save_root.to_str().unwrap()));
+    ) -> GenericResult<GameConfig> {
+        let expand = |key: &str, path: &str| {
+            resolve_path(&defaults.config_dir, path)
+                .map_err(|x| format!("games.{}.{}: {}", name, key, x))
+        };
+        let Settings {
+            save_root,
+            format,
+            level,
+            encrypt,
+            dedup,
+            count,
+            keep,
+            max_total_size,
+            max_age,
+            interval,
+            trigger,
+            debounce,
+        } = settings;
+        let mut save_dir = match save_root.as_deref() {
+            Some(save_dir) => expand("save_root", save_dir)?,
+            None => defaults.save_root.clone(),
+        };
         save_dir.push(name);
+        let encrypt = match encrypt.or_else(|| defaults.encrypt.clone()) {
+            Some(KeySource::File(path)) => Some(KeySource::File(expand(
+                "encrypt.key_file",
+                path.to_str().unwrap(),
+            )?)),
+            encrypt => encrypt,
+        };
+        let mut game_sources: Vec<Source> = vec![];
+        for source in sources {
+            if let Some(alias) = &source.alias {
+                if alias.is_empty() || alias.starts_with('.') || alias.contains(['/', '\\']) {
+                    return Err(format!(
+                        "games.{}.sources: alias {:?} must be a folder name not starting with '.'",
+                        name, alias
+                    )
+                    .into());
+                }
+                if game_sources.iter().any(|x| x.alias.as_ref() == Some(alias)) {
+                    return Err(format!("games.{}.sources: duplicate alias {}", name, alias).into());
+                }
+            }
+            let file_list = FileList::try_new(
+                expand("root", &source.root)?.to_str().unwrap(),
+                source.include,
+                source.exclude,
+            )
+            .map_err(|x| format!("games.{}: {}", name, x))?;
+            game_sources.push(Source::new(source.alias.as_deref(), file_list));
+        }
 
-        GameConfig {
+        Ok(GameConfig {
             name: name.to_owned(),
             save_dir,
-            zip: zip.unwrap_or(defaults.zip),
-            file_list: FileList::new(root, include, exclude),
+            format: format.unwrap_or(defaults.format),
+            // A level only carries over with the format it was set for
+            level: if format.is_some() {
+                level
+            } else {
+                level.or(defaults.level)
+            },
+            encrypt,
+            dedup: dedup.unwrap_or(defaults.dedup),
+            sources: game_sources,
             interval: interval.unwrap_or(defaults.interval),
             count: count.unwrap_or(defaults.count),
+            keep: keep.or(defaults.keep),
+            max_total_size,
+            max_age: max_age.or(defaults.max_age),
+            save_root_limit: defaults.max_total_size.map(|max_total_size| RootLimit {
+                save_root: defaults.save_root.clone(),
+                max_total_size,
+                // Filled in once every game is read
+                games: vec![],
+            }),
+            trigger: trigger.unwrap_or(defaults.trigger),
+            debounce: debounce.unwrap_or(defaults.debounce),
+            process: process.map(|x| x.to_owned()),
+            backup_on_start: backup_on_start.unwrap_or(false),
+        })
+    }
+
+    /// Zip backups of everything under `root`, keeping the latest 3, for tests to
+    /// adjust with struct update syntax
+    #[cfg(test)]
+    pub fn test(name: &str, root: &Path, save_dir: &Path) -> GameConfig {
+        GameConfig {
+            name: name.to_owned(),
+            save_dir: save_dir.to_owned(),
+            format: BackupFormat::Zip(Compression::Deflate),
+            level: None,
+            encrypt: None,
+            dedup: false,
+            sources: vec![Source::new(
+                None,
+                FileList::new(root.to_str().unwrap(), None, None),
+            )],
+            interval: 30,
+            count: 3,
+            keep: None,
+            max_total_size: None,
+            max_age: None,
+            save_root_limit: None,
+            trigger: Trigger::Interval,
+            debounce: 10,
+            process: None,
+            backup_on_start: false,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// A folder of a game as written in the config file, before its paths are expanded
+pub struct SourceConfig {
+    pub alias: Option<String>,
+    pub root: String,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+}
+
+impl SourceConfig {
+    pub fn new(
+        alias: Option<&str>,
+        root: &str,
+        include: Option<Vec<String>>,
+        exclude: Option<Vec<String>>,
+    ) -> Self {
+        SourceConfig {
+            alias: alias.map(|x| x.to_owned()),
+            root: root.to_owned(),
+            include,
+            exclude,
+        }
+    }
+}
+
+/// A folder of a game, stored under `alias` inside its backups
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Source {
+    /// Folder inside backups holding the files, `None` for the top level
+    pub alias: Option<String>,
+    pub file_list: FileList,
+}
+
+impl Source {
+    pub fn new(alias: Option<&str>, file_list: FileList) -> Self {
+        Source {
+            alias: alias.map(|x| x.to_owned()),
+            file_list,
+        }
+    }
+
+    /// Path inside a backup of a file under `root`
+    pub fn backup_path(&self, file_path: &Path) -> PathBuf {
+        let relative_path = file_path.strip_prefix(&self.file_list.root).unwrap();
+        match &self.alias {
+            Some(alias) => Path::new(alias).join(relative_path),
+            None => relative_path.to_owned(),
+        }
+    }
+
+    /// Where a file stored at `backup_path` inside a backup is restored to,
+    /// if it belongs to this source
+    pub fn target(&self, backup_path: &Path) -> Option<PathBuf> {
+        let relative_path = match &self.alias {
+            Some(alias) => backup_path.strip_prefix(alias).ok()?,
+            None => backup_path,
+        };
+        // A file stored at the alias itself is the root, joining would add a separator
+        let target = if relative_path.as_os_str().is_empty() {
+            self.file_list.root.clone()
+        } else {
+            self.file_list.root.join(relative_path)
+        };
+        self.file_list.contains(&target).then_some(target)
+    }
+}
+
+/// Every file of `sources` paired with its path inside a backup
+pub fn source_files(sources: &[Source]) -> impl Iterator<Item = (PathBuf, PathBuf)> + '_ {
+    sources.iter().flat_map(|source| {
+        source.file_list.into_iter().map(move |x| {
+            let backup_path = source.backup_path(&x);
+            (x, backup_path)
+        })
+    })
+}
+
+/// Where a file stored at `backup_path` inside a backup is restored to, if any source includes it
+pub fn source_target(sources: &[Source], backup_path: &Path) -> Option<PathBuf> {
+    sources.iter().find_map(|x| x.target(backup_path))
+}
+
+/// Files under `root` matched by gitignore-style `include` and `exclude` patterns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "FilePatterns", into = "FilePatterns")]
 pub struct FileList {
     pub root: PathBuf,
-    include: Option<Vec<PathBuf>>,
-    exclude: Option<Vec<PathBuf>>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    include_matcher: Option<Gitignore>,
+    exclude_matcher: Option<Gitignore>,
+}
+
+/// Form a `FileList` is saved in, without the compiled patterns
+#[derive(Clone, Serialize, Deserialize)]
+struct FilePatterns {
+    root: PathBuf,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+}
+
+impl From<FilePatterns> for FileList {
+    fn from(patterns: FilePatterns) -> Self {
+        FileList::new(
+            patterns.root.to_str().unwrap(),
+            patterns.include,
+            patterns.exclude,
+        )
+    }
+}
+
+impl From<FileList> for FilePatterns {
+    fn from(file_list: FileList) -> Self {
+        FilePatterns {
+            root: file_list.root,
+            include: file_list.include,
+            exclude: file_list.exclude,
+        }
+    }
+}
+
+impl PartialEq for FileList {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root && self.include == other.include && self.exclude == other.exclude
+    }
 }
 
 impl FileList {
     pub fn new(root: &str, include: Option<Vec<String>>, exclude: Option<Vec<String>>) -> Self {
-        let root_path = PathBuf::from(root);
-        let exclude = if let Some(exclude) = exclude {
-            Some(exclude.iter().map(|x| root_path.join(x)).collect())
-        } else {
-            None
-        };
-        let include = if let Some(include) = include {
-            Some(include.iter().map(|x| PathBuf::from(x)).collect())
-        } else {
-            None
+        Self::try_new(root, include, exclude).log_expect("Invalid include or exclude pattern")
+    }
+
+    /// Compiles the patterns, failing if any of them is not a valid glob
+    pub fn try_new(
+        root: &str,
+        include: Option<Vec<String>>,
+        exclude: Option<Vec<String>>,
+    ) -> GenericResult<Self> {
+        // Back slashes are treated as separators rather than escapes for Windows paths
+        let normalise = |patterns: Option<Vec<String>>| {
+            patterns.map(|x| x.iter().map(|x| x.replace('\\', "/")).collect::<Vec<_>>())
         };
-        Self {
-            root: PathBuf::from(root),
+        let include = normalise(include);
+        let exclude = normalise(exclude);
+        let root = PathBuf::from(root);
+        Ok(Self {
+            include_matcher: build_matcher(&root, include.as_deref())?,
+            exclude_matcher: build_matcher(&root, exclude.as_deref())?,
+            root,
             include,
             exclude,
+        })
+    }
+
+    /// Patterns of files to back up, `None` for everything under `root`
+    pub fn include(&self) -> Option<&[String]> {
+        self.include.as_deref()
+    }
+
+    /// Whether a path under `root` belongs to the include/exclude set
+    pub fn contains(&self, path: &Path) -> bool {
+        let relative_path = match path.strip_prefix(&self.root) {
+            Ok(relative_path) => relative_path,
+            Err(_) => return false,
+        };
+        let is_dir = path.is_dir();
+        if self.excludes(relative_path, is_dir) {
+            return false;
+        }
+        match &self.include_matcher {
+            Some(include) => include
+                .matched_path_or_any_parents(relative_path, is_dir)
+                .is_ignore(),
+            None => true,
         }
     }
+
+    /// Whether a path or any folder above it is excluded. As with gitignore, files
+    /// in an excluded folder can't be included again by a negated pattern.
+    fn excludes(&self, relative_path: &Path, is_dir: bool) -> bool {
+        let exclude = match &self.exclude_matcher {
+            Some(exclude) => exclude,
+            None => return false,
+        };
+        let mut ancestors = relative_path
+            .ancestors()
+            .filter(|x| !x.as_os_str().is_empty());
+        if let Some(path) = ancestors.next() {
+            if exclude.matched(path, is_dir).is_ignore() {
+                return true;
+            }
+        }
+        ancestors.any(|x| exclude.matched(x, true).is_ignore())
+    }
+
+    /// Paths the walk starts from, the fixed leading folders of each include pattern
+    fn start_paths(&self) -> Vec<PathBuf> {
+        let include = match &self.include {
+            Some(include) => include,
+            None => return vec![self.root.clone()],
+        };
+        let mut starts: Vec<PathBuf> = vec![];
+        for pattern in include.iter().filter(|x| !x.starts_with('!')) {
+            let pattern = pattern.trim_end_matches('/');
+            // Patterns without a slash match at any depth
+            let start = if !pattern.contains('/') {
+                self.root.clone()
+            } else {
+                self.root.join(
+                    pattern
+                        .trim_start_matches('/')
+                        .split('/')
+                        .take_while(|x| !x.contains(['*', '?', '[', '{']))
+                        .collect::<PathBuf>(),
+                )
+            };
+            starts.push(start);
+        }
+        starts.sort();
+        starts.dedup_by(|x, y| x.starts_with(&*y));
+        starts
+    }
+}
+
+fn build_matcher(root: &Path, patterns: Option<&[String]>) -> GenericResult<Option<Gitignore>> {
+    let patterns = match patterns {
+        Some(patterns) => patterns,
+        None => return Ok(None),
+    };
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        builder.add_line(None, pattern)?;
+    }
+    Ok(Some(builder.build()?))
 }
 
 impl<'a> IntoIterator for &'a FileList {
@@ -112,92 +620,44 @@ impl<'a> IntoIterator for &'a FileList {
 
     fn into_iter(self) -> Self::IntoIter {
         FileListIterator {
-            file_list: &self,
-            dir_iterators: if self.include.is_none() {
-                vec![fs::read_dir(&self.root).unwrap()]
-            } else {
-                vec![]
-            },
-            include_files: vec![],
-            initialized: false,
+            file_list: self,
+            pending: self.start_paths(),
         }
     }
 }
 
+/// Walks the folders under `root`, skipping excluded folders without reading them
 pub struct FileListIterator<'a> {
     file_list: &'a FileList,
-    dir_iterators: Vec<ReadDir>,
-    include_files: Vec<PathBuf>,
-    initialized: bool,
+    pending: Vec<PathBuf>,
 }
 
 impl<'a> Iterator for FileListIterator<'a> {
     type Item = PathBuf;
     fn next(&mut self) -> Option<Self::Item> {
-        // Add include directory iterators if present
-        if !self.initialized {
-            if let Some(include_list) = &self.file_list.include {
-                for path in include_list {
-                    let path = self.file_list.root.join(path);
-                    if path.is_dir() {
-                        let iterator = path
-                            .read_dir()
-                            .log_expect(format!("Failed to open directory {:?}", &path));
-                        self.dir_iterators.push(iterator);
-                    } else {
-                        self.include_files.push(path);
-                    }
-                }
-            }
-            self.initialized = true;
-        }
-        loop {
-            if let Some(mut read_dir) = self.dir_iterators.pop() {
-                let next = read_dir.next();
-
-                if let Some(entry) = next {
-                    if let Ok(dir_entry) = entry {
-                        let path = dir_entry.path();
-                        let exclude = &self.file_list.exclude;
-
-                        // Check if excluded
-                        if let Some(exclude) = exclude {
-                            if exclude.contains(&path) {
-                                self.dir_iterators.push(read_dir);
-                                continue;
-                            }
-                        }
-
-                        // Add to iterator queue if directory
-                        if path.is_dir() {
-                            let nested_iter = fs::read_dir(&path);
-
-                            if let Ok(nested_iter) = nested_iter {
-                                self.dir_iterators.push(read_dir);
-                                self.dir_iterators.push(nested_iter);
-                            }
-                        } else {
-                            let path = dir_entry.path();
-                            self.dir_iterators.push(read_dir);
-                            return Some(path);
-                        }
-                    }
-                } else {
+        while let Some(path) = self.pending.pop() {
+            if path.is_dir() {
+                let relative_path = path.strip_prefix(&self.file_list.root).unwrap();
+                if self.file_list.excludes(relative_path, true) {
                     continue;
                 }
-            } else {
-                if self.file_list.include.is_none() {
-                    return None;
+                if let Ok(entries) = fs::read_dir(&path) {
+                    self.pending
+                        .extend(entries.filter_map(|x| x.ok()).map(|x| x.path()));
                 }
-                return self.include_files.pop();
+            } else if path.is_file() && self.file_list.contains(&path) {
+                return Some(path);
             }
         }
+        None
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::path::PathBuf;
+    use std::{fs, path::PathBuf};
+
+    use crate::utils::path::test_dir;
 
     use super::FileList;
 
@@ -260,4 +720,65 @@ mod test {
         expected.sort();
         assert_eq!(files, expected)
     }
+
+    #[test]
+    fn test_file_list_globs() {
+        let base = test_dir("glob");
+        for dir in ["saves/slot1", "saves/slot2", "saves/cache", "logs"] {
+            fs::create_dir_all(base.join(dir)).unwrap();
+        }
+        for file in [
+            "saves/slot1/game.sav",
+            "saves/slot1/thumb.png",
+            "saves/slot2/game.sav",
+            "saves/slot2/keep.png",
+            "saves/cache/game.sav",
+            "logs/game.sav",
+            "settings.ini",
+        ] {
+            fs::write(base.join(file), "").unwrap();
+        }
+        let root = base.to_str().unwrap();
+        let files = |include: Option<&[&str]>, exclude: Option<&[&str]>| {
+            let patterns = |x: &[&str]| x.iter().map(|x| x.to_string()).collect();
+            let file_list = FileList::new(root, include.map(patterns), exclude.map(patterns));
+            let mut files: Vec<String> = file_list
+                .into_iter()
+                .map(|x| x.strip_prefix(&base).unwrap().to_str().unwrap().to_owned())
+                .collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(
+            files(Some(&["saves/*/game.sav"]), None),
+            vec![
+                "saves/cache/game.sav",
+                "saves/slot1/game.sav",
+                "saves/slot2/game.sav"
+            ]
+        );
+        // Patterns without a slash match at any depth
+        assert_eq!(
+            files(Some(&["*.ini", "*.png"]), None),
+            vec![
+                "saves/slot1/thumb.png",
+                "saves/slot2/keep.png",
+                "settings.ini"
+            ]
+        );
+        assert_eq!(
+            files(Some(&["saves"]), Some(&["*.png", "!keep.png", "cache/"])),
+            vec![
+                "saves/slot1/game.sav",
+                "saves/slot2/game.sav",
+                "saves/slot2/keep.png"
+            ]
+        );
+        assert_eq!(
+            files(Some(&["**/game.sav", "!logs/*"]), Some(&["saves/slot*"])),
+            vec!["saves/cache/game.sav"]
+        );
+        assert!(FileList::try_new(root, Some(vec!["saves/[z-a]".to_owned()]), None).is_err());
+    }
 }
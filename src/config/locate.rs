@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+use crate::utils::{constants::APP_NAME, utils::GenericResult};
+
+pub const CONFIG_FILE: &str = "config.yaml";
+
+/// Config file to read and the directory the log is written to
+#[derive(Debug, PartialEq)]
+pub struct ConfigLocation {
+    pub path: PathBuf,
+    pub data_dir: PathBuf,
+}
+
+impl ConfigLocation {
+    pub fn new(path: PathBuf, data_dir: PathBuf) -> Self {
+        ConfigLocation { path, data_dir }
+    }
+}
+
+/// Finds the config file given with `--config`, or else `config.yaml` in the
+/// current directory, or else `config.yaml` in the platform config directory
+/// (`$XDG_CONFIG_HOME/save-backup` on Linux)
+pub fn find_config(explicit: Option<&Path>) -> GenericResult<ConfigLocation> {
+    find_config_in(
+        explicit,
+        Path::new("."),
+        dirs::config_dir().map(|x| x.join(APP_NAME)),
+        dirs::data_local_dir().map(|x| x.join(APP_NAME)),
+    )
+}
+
+/// A config in the current directory keeps its log beside it as before, any
+/// other config logs to the platform data directory
+fn find_config_in(
+    explicit: Option<&Path>,
+    current_dir: &Path,
+    config_dir: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+) -> GenericResult<ConfigLocation> {
+    let data_dir_or = |path: &Path| {
+        data_dir
+            .clone()
+            .unwrap_or_else(|| path.parent().unwrap_or(current_dir).to_owned())
+    };
+    if let Some(path) = explicit {
+        return Ok(ConfigLocation::new(path.to_owned(), data_dir_or(path)));
+    }
+
+    let local = current_dir.join(CONFIG_FILE);
+    if local.is_file() {
+        return Ok(ConfigLocation::new(local, current_dir.to_owned()));
+    }
+    let mut searched = vec![local];
+    if let Some(config_dir) = config_dir {
+        let path = config_dir.join(CONFIG_FILE);
+        if path.is_file() {
+            return Ok(ConfigLocation::new(path.clone(), data_dir_or(&path)));
+        }
+        searched.push(path);
+    }
+    Err(format!(
+        "No config file found, looked for {}. Pass one with --config <path>",
+        searched
+            .iter()
+            .map(|x| format!("{:?}", x))
+            .collect::<Vec<_>>()
+            .join(" and ")
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::utils::path::test_dir;
+
+    use super::{find_config_in, ConfigLocation};
+
+    #[test]
+    fn test_find_config() {
+        let base = test_dir("locate");
+        let current_dir = base.join("current");
+        let config_dir = base.join("config/save-backup");
+        let data_dir = base.join("data/save-backup");
+        fs::create_dir_all(&current_dir).unwrap();
+        fs::create_dir_all(&config_dir).unwrap();
+        let find = |explicit: Option<&Path>| {
+            find_config_in(
+                explicit,
+                &current_dir,
+                Some(config_dir.clone()),
+                Some(data_dir.clone()),
+            )
+        };
+
+        let error = find(None).unwrap_err().to_string();
+        assert!(error.contains("current/config.yaml"), "{}", error);
+        assert!(error.contains("--config"), "{}", error);
+
+        fs::write(config_dir.join("config.yaml"), "games: {}\n").unwrap();
+        assert_eq!(
+            find(None).unwrap(),
+            ConfigLocation::new(config_dir.join("config.yaml"), data_dir.clone())
+        );
+
+        // A config in the current directory takes precedence and keeps its log beside it
+        fs::write(current_dir.join("config.yaml"), "games: {}\n").unwrap();
+        assert_eq!(
+            find(None).unwrap(),
+            ConfigLocation::new(current_dir.join("config.yaml"), current_dir.clone())
+        );
+
+        let explicit = base.join("elsewhere.yaml");
+        assert_eq!(
+            find(Some(&explicit)).unwrap(),
+            ConfigLocation::new(explicit.clone(), data_dir.clone())
+        );
+        assert_eq!(
+            find_config_in(Some(&explicit), &current_dir, None, None).unwrap(),
+            ConfigLocation::new(explicit, base.clone())
+        );
+    }
+}
@@ -1,98 +1,340 @@
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufReader};
+use std::path::Path;
 
-use serde_yaml::Value;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 
-use crate::utils::log::{log, LogExpectOption, LogExpectResult};
+use crate::utils::log::log;
+use crate::utils::utils::GenericResult;
 
-use super::config_types::{GameConfig, SharedConfig};
+use super::config_types::{
+    BackupFormat, Compression, GameConfig, KeySource, Retention, Settings, SharedConfig,
+    SourceConfig, Trigger,
+};
 
-const SAVE: &str = "save_root";
-const ZIP: &str = "zip";
-const COUNT: &str = "count";
-const ROOT: &str = "root";
-const INCLUDE: &str = "include";
-const EXCLUDE: &str = "exclude";
-const INTERVAL: &str = "interval";
+/// Layout of `config.yaml`, with shared defaults at the top level and each game under `games`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    save_root: Option<String>,
+    /// Older configs only choose between a zip and a folder
+    zip: Option<bool>,
+    format: Option<BackupFormat>,
+    level: Option<i32>,
+    encrypt: Option<KeySource>,
+    dedup: Option<bool>,
+    count: Option<u64>,
+    keep: Option<Retention>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    max_total_size: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    max_age: Option<i64>,
+    interval: Option<i64>,
+    trigger: Option<Trigger>,
+    debounce: Option<u64>,
+    games: Games,
+}
 
-const SHARED_FIELDS: &[&str] = &[SAVE, ZIP, COUNT, INTERVAL];
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GameFields {
+    root: Option<String>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    sources: Option<Vec<SourceFields>>,
+    save_root: Option<String>,
+    zip: Option<bool>,
+    format: Option<BackupFormat>,
+    level: Option<i32>,
+    encrypt: Option<KeySource>,
+    dedup: Option<bool>,
+    count: Option<u64>,
+    keep: Option<Retention>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    max_total_size: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    max_age: Option<i64>,
+    interval: Option<i64>,
+    trigger: Option<Trigger>,
+    debounce: Option<u64>,
+    process: Option<String>,
+    backup_on_start: Option<bool>,
+}
 
-pub fn read_config_from_file(file: &str) -> Vec<GameConfig> {
-    let file =
-        File::open(file).log_expect("Failed to find config.yaml file in application directory");
-    let mut reader = BufReader::new(file);
-    read_config(&mut reader)
+/// One of several folders of a game, stored under `alias` inside backups
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourceFields {
+    root: String,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    alias: String,
 }
 
-fn collect_string_sequence(sequence: &Value) -> Option<Vec<String>> {
-    if let Some(sequence) = sequence.as_sequence() {
-        return Some(
-            sequence
-                .iter()
-                .map(|x| {
-                    x.as_str()
-                        .log_expect("Invalid file in config file")
-                        .to_string()
-                })
-                .collect::<Vec<String>>(),
-        );
-    } else {
-        return None;
+/// Games in the order they appear in the config file
+struct Games(Vec<(String, GameFields)>);
+
+impl<'de> Deserialize<'de> for Games {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct GamesVisitor;
+
+        impl<'de> Visitor<'de> for GamesVisitor {
+            type Value = Games;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of game names to game settings")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Games, A::Error> {
+                let mut games: Vec<(String, GameFields)> = vec![];
+                while let Some(name) = map.next_key::<String>()? {
+                    if games.iter().any(|(x, _)| x == &name) {
+                        return Err(de::Error::custom(format!("duplicate game {}", name)));
+                    }
+                    games.push((name, map.next_value()?));
+                }
+                Ok(Games(games))
+            }
+        }
+
+        deserializer.deserialize_map(GamesVisitor)
     }
 }
 
-pub fn read_config<R: io::Read>(reader: &mut R) -> Vec<GameConfig> {
-    log("Reading shared config");
-    let config: serde_yaml::Value = serde_yaml::from_reader(reader).log_expect("Invalid config");
+/// A number of bytes, either plain or with a unit such as `500MB` or `2 GiB`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Size {
+    Bytes(u64),
+    Text(String),
+}
 
-    let shared_config = SharedConfig::new(
-        config[SAVE].as_str(),
-        config[ZIP].as_bool(),
-        config[COUNT].as_u64(),
-        config[INTERVAL].as_i64(),
-    );
-    log(&shared_config);
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(size) if size > 0 => Ok(Some(size)),
+        Size::Bytes(size) => Err(de::Error::custom(format!("invalid size {}", size))),
+        Size::Text(size) => size_bytes(&size)
+            .map(Some)
+            .ok_or_else(|| de::Error::custom(format!("invalid size {}", size))),
+    }
+}
 
-    let mut configs: Vec<GameConfig> = Vec::new();
+/// A duration written with a unit, such as `90d` or `12h`, read as seconds
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<i64>, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+    duration_seconds(&duration)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("invalid duration {}", duration)))
+}
 
-    log("Reading individual configs");
-    for field in config.as_mapping().unwrap().iter() {
-        if SHARED_FIELDS.contains(&field.0.as_str().log_expect("Invalid field in config file")) {
-            continue;
-        }
+/// Reads and checks a config file, naming the file in any error
+pub fn load_config(path: &Path) -> GenericResult<Vec<GameConfig>> {
+    let file = File::open(path)
+        .map_err(|x| format!("Failed to open config file {}: {}", path.display(), x))?;
+    read_config(
+        &mut BufReader::new(file),
+        path.parent().unwrap_or(Path::new(".")),
+    )
+    .map_err(|x| format!("Invalid config file {}: {}", path.display(), x).into())
+}
+
+pub fn find_config(configs: Vec<GameConfig>, name: &str) -> GenericResult<GameConfig> {
+    configs
+        .into_iter()
+        .find(|x| x.name == name)
+        .ok_or_else(|| format!("No game named {} in config", name).into())
+}
 
-        let name = field.0.as_str().log_expect("Invalid name in config file");
+fn choose_format(format: Option<BackupFormat>, zip: Option<bool>) -> Option<BackupFormat> {
+    format.or_else(|| {
+        zip.map(|zip| {
+            if zip {
+                BackupFormat::Zip(Compression::Deflate)
+            } else {
+                BackupFormat::Folder
+            }
+        })
+    })
+}
 
-        let save_dir = field.1[SAVE].as_str();
+fn size_bytes(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|x: char| !x.is_ascii_digit() && x != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    let size = (number * multiplier as f64) as u64;
+    (size > 0).then_some(size)
+}
 
-        let zip = field.1[ZIP].as_bool();
+fn duration_seconds(duration: &str) -> Option<i64> {
+    let duration = duration.trim();
+    let (split, _) = duration.char_indices().last()?;
+    let (number, unit) = duration.split_at(split);
+    let multiplier = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let seconds = number.trim().parse::<i64>().ok()?.checked_mul(multiplier)?;
+    (seconds > 0).then_some(seconds)
+}
 
-        let root = field.1[ROOT].as_str().expect("Invalid root in config file");
+/// Checks settings that are only invalid in combination
+fn check_format(config: &GameConfig) -> GenericResult<()> {
+    if config.encrypt.is_some() && (config.dedup || config.format == BackupFormat::Folder) {
+        return Err(format!(
+            "games.{}.encrypt: encryption requires a zip or tar format without dedup",
+            &config.name
+        )
+        .into());
+    }
+    if let Some(level) = config.level {
+        if !config.format.supports_level(level) {
+            return Err(format!(
+                "games.{}.level: invalid level {} for format {}",
+                &config.name,
+                level,
+                config.format.name()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
 
-        let include = collect_string_sequence(&field.1[INCLUDE]);
+/// Explains the most likely cause of an error in a config written before games
+/// were moved under `games`
+fn legacy_hint(contents: &str) -> Option<&'static str> {
+    let value: serde_yaml::Value = serde_yaml::from_str(contents).ok()?;
+    let mapping = value.as_mapping()?;
+    let legacy = !mapping.contains_key(&"games".into())
+        && mapping.iter().any(|(_, x)| x.get("root").is_some());
+    legacy.then_some("games must now be listed under a top level `games:` key")
+}
 
-        let exclude = collect_string_sequence(&field.1[EXCLUDE]);
+/// Reads the games in a config, with relative paths read from `config_dir`
+pub fn read_config<R: io::Read>(
+    reader: &mut R,
+    config_dir: &Path,
+) -> GenericResult<Vec<GameConfig>> {
+    log("Reading shared config");
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let config: ConfigFile =
+        serde_yaml::from_str(&contents).map_err(|x| match legacy_hint(&contents) {
+            Some(hint) => format!("{} ({})", x, hint),
+            None => x.to_string(),
+        })?;
 
-        let count = field.1[COUNT].as_u64();
+    let shared_config = SharedConfig::new(
+        Settings {
+            save_root: config.save_root,
+            format: choose_format(config.format, config.zip),
+            level: config.level,
+            encrypt: config.encrypt,
+            dedup: config.dedup,
+            count: config.count,
+            keep: config.keep,
+            max_total_size: config.max_total_size,
+            max_age: config.max_age,
+            interval: config.interval,
+            trigger: config.trigger,
+            debounce: config.debounce,
+        },
+        config_dir,
+    )?;
+    log(&shared_config);
 
-        let interval = field.1[INTERVAL].as_i64();
+    let mut configs: Vec<GameConfig> = Vec::new();
 
+    log("Reading individual configs");
+    for (name, mut game) in config.games.0 {
+        let sources = game_sources(&name, &mut game)?;
         let game_config = GameConfig::with_defaults(
-            name,
-            save_dir,
-            zip,
-            root,
-            include,
-            exclude,
-            interval,
-            count,
+            &name,
+            Settings {
+                save_root: game.save_root,
+                format: choose_format(game.format, game.zip),
+                level: game.level,
+                encrypt: game.encrypt,
+                dedup: game.dedup,
+                count: game.count,
+                keep: game.keep,
+                max_total_size: game.max_total_size,
+                max_age: game.max_age,
+                interval: game.interval,
+                trigger: game.trigger,
+                debounce: game.debounce,
+            },
+            sources,
+            game.process.as_deref(),
+            game.backup_on_start,
             &shared_config,
-        );
+        )?;
+        check_format(&game_config)?;
         log(&game_config);
         configs.push(game_config);
     }
 
-    configs
+    let shared_games: Vec<String> = configs
+        .iter()
+        .filter(|x| x.save_dir.parent() == Some(&shared_config.save_root))
+        .map(|x| x.name.clone())
+        .collect();
+    for config in &mut configs {
+        if let Some(limit) = &mut config.save_root_limit {
+            limit.games = shared_games.clone();
+        }
+    }
+    Ok(configs)
+}
+
+/// Sources of a game, either the single `root` or each entry of `sources`
+fn game_sources(name: &str, game: &mut GameFields) -> GenericResult<Vec<SourceConfig>> {
+    match (game.root.take(), game.sources.take()) {
+        (Some(root), None) => Ok(vec![SourceConfig::new(
+            None,
+            &root,
+            game.include.take(),
+            game.exclude.take(),
+        )]),
+        (None, Some(sources)) => {
+            if game.include.is_some() || game.exclude.is_some() {
+                return Err(format!(
+                    "games.{}: include and exclude go in each entry of sources",
+                    name
+                )
+                .into());
+            }
+            if sources.is_empty() {
+                return Err(format!("games.{}.sources: no sources given", name).into());
+            }
+            Ok(sources
+                .into_iter()
+                .map(|x| SourceConfig::new(Some(&x.alias), &x.root, x.include, x.exclude))
+                .collect())
+        }
+        (Some(_), Some(_)) => {
+            Err(format!("games.{}: root and sources can't both be set", name).into())
+        }
+        (None, None) => Err(format!("games.{}: missing root or sources", name).into()),
+    }
 }
 
 /*
@@ -101,9 +343,14 @@ TESTS
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{
+        env,
+        path::{Path, PathBuf},
+    };
 
-    use crate::config::config_types::{FileList, GameConfig};
+    use crate::config::config_types::{
+        BackupFormat, Compression, FileList, GameConfig, KeySource, Retention, Source, Trigger,
+    };
 
     #[test]
     fn test_config_no_defaults() {
@@ -113,28 +360,63 @@ zip: true
 interval: 60
 count: 10
 
-elden-ring:
-  root: "%APPDATA%/EldenRing/12345"
-  save_root: ~/Documents/elden-ring-backups/
-  include:
-    - ER0000.sl2
-  interval: 30
-  count: 6 
+games:
+  elden-ring:
+    root: "%APPDATA%/EldenRing/12345"
+    save_root: ~/Documents/elden-ring-backups/
+    format: tar.zst
+    level: 19
+    dedup: true
+    include:
+      - ER0000.sl2
+    interval: 30
+    count: 6 
+    keep:
+      last: 5
+      daily: 7
+      monthly: 12
+    trigger: watch
+    debounce: 5
+    process: eldenring.exe
+    backup_on_start: true
 "#;
-        let configs = crate::config::parse::read_config(&mut config_str.as_bytes());
+        env::set_var("APPDATA", "/appdata");
+        let home = dirs::home_dir().unwrap();
+        let configs =
+            crate::config::parse::read_config(&mut config_str.as_bytes(), Path::new("")).unwrap();
         assert_eq!(
             configs,
             vec![GameConfig {
                 name: "elden-ring".to_owned(),
-                save_dir: PathBuf::from("~/Documents/elden-ring-backups/elden-ring"),
-                zip: true,
-                file_list: FileList::new(
-                    "%APPDATA%/EldenRing/12345",
-                    Some(vec!["ER0000.sl2".to_owned()]),
-                    None
-                ),
+                save_dir: home.join("Documents/elden-ring-backups/elden-ring"),
+                format: BackupFormat::TarZst,
+                level: Some(19),
+                encrypt: None,
+                dedup: true,
+                sources: vec![Source::new(
+                    None,
+                    FileList::new(
+                        "/appdata/EldenRing/12345",
+                        Some(vec!["ER0000.sl2".to_owned()]),
+                        None
+                    )
+                )],
                 interval: 30,
-                count: 6
+                count: 6,
+                keep: Some(Retention {
+                    last: 5,
+                    hourly: 0,
+                    daily: 7,
+                    weekly: 0,
+                    monthly: 12
+                }),
+                max_total_size: None,
+                max_age: None,
+                save_root_limit: None,
+                trigger: Trigger::Watch,
+                debounce: 5,
+                process: Some("eldenring.exe".to_owned()),
+                backup_on_start: true
             }]
         );
     }
@@ -146,21 +428,224 @@ save_root: ~/save-game-backups
 zip: true
 interval: 60
 count: 10
+encrypt:
+  key_file: ~/save-backup.key
 
-elden-ring:
-  root: "%APPDATA%/EldenRing/12345"
+games:
+  elden-ring:
+    root: "%APPDATA%/EldenRing/12345"
 "#;
-        let configs = crate::config::parse::read_config(&mut config_str.as_bytes());
+        env::set_var("APPDATA", "/appdata");
+        let home = dirs::home_dir().unwrap();
+        let configs =
+            crate::config::parse::read_config(&mut config_str.as_bytes(), Path::new("")).unwrap();
         assert_eq!(
             configs,
             vec![GameConfig {
                 name: "elden-ring".to_owned(),
-                save_dir: PathBuf::from("~/save-game-backups/elden-ring"),
-                zip: true,
-                file_list: FileList::new("%APPDATA%/EldenRing/12345", None, None),
+                save_dir: home.join("save-game-backups/elden-ring"),
+                format: BackupFormat::Zip(Compression::Deflate),
+                level: None,
+                encrypt: Some(KeySource::File(home.join("save-backup.key"))),
+                dedup: false,
+                sources: vec![Source::new(
+                    None,
+                    FileList::new("/appdata/EldenRing/12345", None, None)
+                )],
                 interval: 60,
-                count: 10
+                count: 10,
+                keep: None,
+                max_total_size: None,
+                max_age: None,
+                save_root_limit: None,
+                trigger: Trigger::Interval,
+                debounce: 10,
+                process: None,
+                backup_on_start: false
             }]
         );
     }
+
+    #[test]
+    fn test_config_sources() {
+        let config_str = r#"
+games:
+  elden-ring:
+    sources:
+      - root: /games/EldenRing/saves
+        alias: saves
+        exclude:
+          - "*.bak"
+      - root: /games/EldenRing/settings
+        include:
+          - GraphicsConfig.xml
+        alias: settings
+"#;
+        let configs =
+            crate::config::parse::read_config(&mut config_str.as_bytes(), Path::new("")).unwrap();
+        assert_eq!(
+            configs[0].sources,
+            vec![
+                Source::new(
+                    Some("saves"),
+                    FileList::new(
+                        "/games/EldenRing/saves",
+                        None,
+                        Some(vec!["*.bak".to_owned()])
+                    )
+                ),
+                Source::new(
+                    Some("settings"),
+                    FileList::new(
+                        "/games/EldenRing/settings",
+                        Some(vec!["GraphicsConfig.xml".to_owned()]),
+                        None
+                    )
+                )
+            ]
+        );
+
+        let error = config_error("games:\n  thing:\n    sources:\n      - root: a\n");
+        assert!(error.contains("missing field `alias`"), "{}", error);
+        let error = config_error(
+            "games:\n  thing:\n    root: a\n    sources:\n      - root: b\n        alias: b\n",
+        );
+        assert!(error.contains("root and sources"), "{}", error);
+        let error = config_error(
+            "games:\n  thing:\n    sources:\n      - {root: a, alias: x}\n      - {root: b, alias: x}\n",
+        );
+        assert!(error.contains("duplicate alias x"), "{}", error);
+        let error = config_error("games:\n  thing:\n    sources:\n      - {root: a, alias: a/b}\n");
+        assert!(error.contains("games.thing.sources"), "{}", error);
+        let error = config_error("games:\n  thing:\n    count: 3\n");
+        assert!(error.contains("missing root or sources"), "{}", error);
+    }
+
+    fn config_error(config_str: &str) -> String {
+        crate::config::parse::read_config(&mut config_str.as_bytes(), Path::new(""))
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let error = config_error(
+            r#"
+count: 10
+games:
+  elden-ring:
+    root: "%APPDATA%/EldenRing/12345"
+    exlude:
+      - steam_autocloud.vdf
+"#,
+        );
+        assert!(error.contains("games.elden-ring"), "{}", error);
+        assert!(error.contains("unknown field `exlude`"), "{}", error);
+        assert!(error.contains("line 6"), "{}", error);
+
+        let error = config_error("coutn: 10\ngames: {}\n");
+        assert!(error.contains("unknown field `coutn`"), "{}", error);
+
+        let error = config_error("games:\n  thing:\n    root: a\n    max_age: 90 days\n");
+        assert!(error.contains("invalid duration 90 days"), "{}", error);
+
+        let error =
+            config_error("games:\n  thing:\n    root: a\n    format: folder\n    level: 3\n");
+        assert!(error.contains("games.thing.level"), "{}", error);
+
+        let error = config_error("games:\n  thing:\n    root: '%SAVE_BACKUP_UNSET_DIR%/saves'\n");
+        assert!(error.contains("games.thing.root"), "{}", error);
+        assert!(error.contains("SAVE_BACKUP_UNSET_DIR"), "{}", error);
+
+        let error = config_error("games:\n  thing:\n    root: a\n    exclude: ['[z-a]']\n");
+        assert!(error.contains("games.thing"), "{}", error);
+        assert!(error.contains("[z-a]"), "{}", error);
+
+        // Games at the top level are no longer accepted
+        let error = config_error("count: 10\nelden-ring:\n  root: a\n");
+        assert!(error.contains("`games:`"), "{}", error);
+    }
+
+    #[test]
+    fn test_size_and_duration() {
+        use super::{duration_seconds, size_bytes};
+
+        assert_eq!(size_bytes("1024"), Some(1024));
+        assert_eq!(size_bytes("500MB"), Some(500 * 1024 * 1024));
+        assert_eq!(size_bytes("1.5 GiB"), Some(3 * 512 * 1024 * 1024));
+        assert_eq!(size_bytes("10 parsecs"), None);
+        assert_eq!(duration_seconds("90d"), Some(90 * 24 * 60 * 60));
+        assert_eq!(duration_seconds("12h"), Some(12 * 60 * 60));
+        assert_eq!(duration_seconds("90"), None);
+        assert_eq!(duration_seconds("90日"), None);
+        assert_eq!(duration_seconds("-5d"), None);
+        assert_eq!(duration_seconds("0h"), None);
+        assert_eq!(size_bytes("0MB"), None);
+        assert_eq!(size_bytes("-5MB"), None);
+
+        let error = config_error("games:\n  thing:\n    root: a\n    max_age: 90日\n");
+        assert!(error.contains("invalid duration 90日"), "{}", error);
+        let error = config_error("games:\n  thing:\n    root: a\n    max_total_size: 0\n");
+        assert!(error.contains("invalid size 0"), "{}", error);
+    }
+
+    #[test]
+    fn test_save_root_limit() {
+        use crate::config::config_types::RootLimit;
+
+        let config_str = r#"
+save_root: /backups
+max_total_size: 1GB
+games:
+  shared:
+    root: /games/shared
+  own:
+    root: /games/own
+    save_root: /elsewhere
+"#;
+        let configs =
+            crate::config::parse::read_config(&mut config_str.as_bytes(), Path::new("")).unwrap();
+        // Games with their own save_root still trim the top level one
+        let limit = Some(RootLimit {
+            save_root: PathBuf::from("/backups"),
+            max_total_size: 1 << 30,
+            games: vec!["shared".to_owned()],
+        });
+        assert_eq!(configs[0].save_root_limit, limit);
+        assert_eq!(configs[1].save_dir, PathBuf::from("/elsewhere/own"));
+        assert_eq!(configs[1].save_root_limit, limit);
+    }
+
+    #[test]
+    fn test_relative_paths() {
+        let config_str = r#"
+save_root: backups
+encrypt:
+  key_file: secret.key
+games:
+  thing:
+    root: saves
+  absolute:
+    root: /games/absolute
+    save_root: /elsewhere
+"#;
+        let configs =
+            crate::config::parse::read_config(&mut config_str.as_bytes(), Path::new("/configs"))
+                .unwrap();
+        // Relative paths are read from the config's folder, not the current directory
+        assert_eq!(
+            configs[0].sources[0].file_list.root,
+            PathBuf::from("/configs/saves")
+        );
+        assert_eq!(configs[0].save_dir, PathBuf::from("/configs/backups/thing"));
+        assert_eq!(
+            configs[0].encrypt,
+            Some(KeySource::File(PathBuf::from("/configs/secret.key")))
+        );
+        assert_eq!(
+            configs[1].sources[0].file_list.root,
+            PathBuf::from("/games/absolute")
+        );
+        assert_eq!(configs[1].save_dir, PathBuf::from("/elsewhere/absolute"));
+    }
 }
@@ -1,25 +1,56 @@
 #![cfg_attr(not(test), windows_subsystem = "windows")]
-use std::thread;
+use std::{env, path::Path, thread};
 
 use backup::run::start_backup_loop;
+use commands::args::{parse_args, split_config_arg, Command};
+use config::{
+    locate::{find_config, ConfigLocation},
+    parse::load_config,
+};
 use notify_rust::Notification;
 use utils::{
     log::{log, reset_log},
+    path::{remove_private_temp_files, set_data_dir},
     process::replace_instance,
     utils::GenericResult,
 };
 
 mod config {
+    pub mod check;
     pub mod config_types;
+    pub mod locate;
     pub mod parse;
 }
 mod backup {
     pub mod backup_types;
+    pub mod diff;
+    pub mod encrypt;
     pub mod file_data;
+    pub mod game_process;
+    pub mod list;
+    pub mod manifest;
+    pub mod pin;
+    pub mod restore;
+    pub mod retention;
     pub mod run;
+    pub mod store;
+    pub mod tarball;
+    pub mod verify;
+    pub mod watch;
+}
+mod commands {
+    pub mod args;
+    pub mod backup;
+    pub mod check;
+    pub mod diff;
+    pub mod list;
+    pub mod pin;
+    pub mod restore;
+    pub mod verify;
 }
 mod utils {
     pub mod constants;
+    pub mod lock;
     pub mod log;
     pub mod path;
     pub mod process;
@@ -36,10 +67,82 @@ fn main() -> GenericResult<()> {
             .show()
             .unwrap();
     }));
+    #[cfg(windows)]
+    if env::args().len() > 1 {
+        attach_console();
+    }
+    run_command().inspect_err(|x| log(x))
+}
+
+fn run_command() -> GenericResult<()> {
+    let (config_path, args) = split_config_arg(env::args().skip(1))?;
+    let command = parse_args(args.into_iter())?;
+    // check-config can name the file to check instead of using --config
+    let config_path = match &command {
+        Command::CheckConfig { path: Some(path) } => Some(path.clone()),
+        _ => config_path,
+    };
+    let location = locate(config_path.as_deref())?;
+    match command {
+        Command::Run => run(&location.path),
+        Command::Restore { name, selector } => {
+            let configs = load_config(&location.path)?;
+            commands::restore::run_restore(configs, &name, &selector)
+        }
+        Command::BackupNow { name, label } => {
+            let configs = load_config(&location.path)?;
+            commands::backup::run_backup_now(configs, name.as_deref(), label.as_deref())
+        }
+        Command::CheckConfig { .. } => commands::check::run_check_config(&location.path),
+        Command::Diff { name, from, to } => {
+            let configs = load_config(&location.path)?;
+            commands::diff::run_diff(configs, &name, &from, &to)
+        }
+        Command::List { name, json } => {
+            let configs = load_config(&location.path)?;
+            commands::list::run_list(configs, name.as_deref(), json)
+        }
+        Command::Pin { name, selector } => {
+            let configs = load_config(&location.path)?;
+            commands::pin::run_pin(configs, &name, &selector, true)
+        }
+        Command::Unpin { name, selector } => {
+            let configs = load_config(&location.path)?;
+            commands::pin::run_pin(configs, &name, &selector, false)
+        }
+        Command::Extract {
+            name,
+            selector,
+            target,
+            path,
+        } => {
+            let configs = load_config(&location.path)?;
+            commands::restore::run_extract(configs, &name, &selector, &target, path.as_deref())
+        }
+        Command::PruneSnapshots { name } => {
+            let configs = load_config(&location.path)?;
+            commands::restore::run_prune_snapshots(configs, name.as_deref())
+        }
+        Command::Verify { name } => {
+            let configs = load_config(&location.path)?;
+            commands::verify::run_verify(configs, name.as_deref())
+        }
+    }
+}
+
+/// Finds the config file and sends the log to the data directory that goes with it
+fn locate(config_path: Option<&Path>) -> GenericResult<ConfigLocation> {
+    let location = find_config(config_path)?;
+    set_data_dir(&location.data_dir)?;
+    Ok(location)
+}
+
+fn run(config_path: &Path) -> GenericResult<()> {
     reset_log()?;
-    replace_instance();
+    replace_instance()?;
+    remove_private_temp_files()?;
 
-    let configs = config::parse::read_config_from_file("config.yaml");
+    let configs = load_config(config_path)?;
     let mut threads = vec![];
     for config in configs {
         let thread = thread::spawn(move || {
@@ -52,3 +155,18 @@ fn main() -> GenericResult<()> {
     }
     Ok(())
 }
+
+/// Connects to the console of the shell a command was started from, as the windows
+/// subsystem starts without one and anything printed would otherwise be lost
+#[cfg(windows)]
+fn attach_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    // Fails harmlessly when started without a console, e.g. from a shortcut
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
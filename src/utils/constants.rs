@@ -1 +1,5 @@
 pub static DATE_FORMAT: &str = r"%Y-%m-%d_%H-%M-%S%z";
+/// Prefix of backups still being written, renamed away once complete
+pub static TEMP_PREFIX: &str = ".tmp-";
+/// Name of the folders holding the config and log in platform directories
+pub static APP_NAME: &str = "save-backup";
@@ -0,0 +1,45 @@
+use std::{fs, io, path::Path};
+
+/// Exclusive lock on a file, shared with other threads and processes, released when dropped
+pub struct FileLock {
+    // Closing the file releases the lock
+    _file: fs::File,
+}
+
+impl FileLock {
+    /// Waits until nothing else holds the lock on `path`, creating the file if needed
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        file.lock()?;
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread, time::Duration};
+
+    use crate::utils::{path::test_dir, utils::GenericResult};
+
+    use super::FileLock;
+
+    #[test]
+    fn test_file_lock() -> GenericResult<()> {
+        let path = test_dir("lock").join(".lock");
+        let lock = FileLock::acquire(&path)?;
+        let (sender, receiver) = mpsc::channel();
+        let waiter = thread::spawn(move || {
+            let _lock = FileLock::acquire(&path).unwrap();
+            sender.send(()).unwrap();
+        });
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(lock);
+        receiver.recv_timeout(Duration::from_secs(5))?;
+        waiter.join().unwrap();
+        Ok(())
+    }
+}
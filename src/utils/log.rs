@@ -1,23 +1,28 @@
 use chrono::Local;
 
-use super::utils::GenericResult;
+use super::{path::data_dir, utils::GenericResult};
 use std::{
     fmt::{Debug, Display},
     fs::File,
     io::Write,
+    path::PathBuf,
 };
 
 static LOG_FILE: &str = "log.txt";
 
+fn log_path() -> PathBuf {
+    data_dir().join(LOG_FILE)
+}
+
 pub fn reset_log() -> GenericResult<()> {
-    let file = File::options().create(true).write(true).open(LOG_FILE)?;
+    let file = File::options().create(true).write(true).open(log_path())?;
     file.set_len(0)?;
 
     Ok(())
 }
 
 pub fn log<T: ToString>(text: T) {
-    if let Ok(mut file) = File::options().create(true).append(true).open(LOG_FILE) {
+    if let Ok(mut file) = File::options().create(true).append(true).open(log_path()) {
         let string = text.to_string();
         let time_string = Local::now().to_string();
         file.write(time_string.as_bytes()).unwrap();
@@ -37,22 +42,6 @@ impl<T, E: Debug> LogExpectResult<T, E> for Result<T, E> {
     }
 }
 
-impl<T> LogExpectOption<T> for Option<T> {
-    fn log_expect<M: Display>(self, message: M) -> T {
-        match self {
-            Some(x) => x,
-            None => {
-                log(message);
-                self.unwrap()
-            }
-        }
-    }
-}
-
 pub trait LogExpectResult<T, E: Debug> {
     fn log_expect<M: Display>(self, message: M) -> T;
 }
-
-pub trait LogExpectOption<T> {
-    fn log_expect<M: Display>(self, message: M) -> T;
-}
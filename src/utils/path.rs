@@ -1,20 +1,225 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::OnceLock,
+};
+
 use chrono::ParseError;
+use sysinfo::{Pid, SystemExt};
+
+use super::{
+    constants::{DATE_FORMAT, TEMP_PREFIX},
+    process::process_system,
+    utils::GenericResult,
+};
 
-use super::{constants::DATE_FORMAT, log::LogExpectResult};
+/// Directory holding the log and private scratch files, the current directory until set
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// Folder under the data directory for archives before encryption and decrypted copies
+const PRIVATE_TEMP_DIR: &str = ".save-backup-tmp";
 
 pub fn get_backup_time(name: &str, filename: &str) -> Result<i64, ParseError> {
     let date_string = &filename[(name.len() + 1)..];
-    let date = chrono::DateTime::parse_from_str(date_string, DATE_FORMAT)
-        .log_expect(format!("Failed to read timestamp for {}", &filename))
-        .timestamp();
+    let date = chrono::DateTime::parse_from_str(date_string, DATE_FORMAT)?.timestamp();
     Ok(date)
 }
 
+/// Path a file or directory is written to before being renamed to `path`
+pub fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    path.with_file_name(format!("{}{}", TEMP_PREFIX, file_name))
+}
+
+/// Uses `dir` for the log and scratch files from now on, creating it if needed
+pub fn set_data_dir(dir: &Path) -> GenericResult<()> {
+    fs::create_dir_all(dir)?;
+    DATA_DIR
+        .set(dir.to_owned())
+        .map_err(|_| "Data directory is already set".into())
+}
+
+pub fn data_dir() -> PathBuf {
+    DATA_DIR
+        .get()
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Empty scratch folder for a test, outside the source tree
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join("save-backup-tests").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Path for a scratch copy of `path` in a folder only the current user can read,
+/// as the copy may hold the plaintext of an encrypted backup
+pub fn private_temp_path(path: &Path) -> io::Result<PathBuf> {
+    let dir = data_dir().join(PRIVATE_TEMP_DIR);
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        builder.mode(0o700);
+        builder.create(&dir)?;
+        // Also tighten a folder created before with looser permissions
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    #[cfg(not(unix))]
+    builder.create(&dir)?;
+
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    Ok(dir.join(format!("{}-{}", process::id(), file_name)))
+}
+
+/// Removes scratch copies left behind by processes that crashed, leaving those of
+/// commands still running
+pub fn remove_private_temp_files() -> io::Result<()> {
+    remove_stale_temp_files(&data_dir().join(PRIVATE_TEMP_DIR))
+}
+
+fn remove_stale_temp_files(dir: &Path) -> io::Result<()> {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    let system = process_system();
+    for entry in entries.filter_map(|x| x.ok()) {
+        // Named `{pid}-{file name}` by `private_temp_path`
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let pid = file_name
+            .split_once('-')
+            .and_then(|(pid, _)| pid.parse::<Pid>().ok());
+        if pid.is_some_and(|x| system.process(x).is_none()) {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Total size of the files in a directory and its subdirectories
+pub fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in path.read_dir()? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Expands `path` and reads it relative to `base` unless it is absolute
+pub fn resolve_path(base: &Path, path: &str) -> Result<PathBuf, String> {
+    Ok(base.join(expand_path(path)?))
+}
+
+/// Expands `~`, `$VAR`, `${VAR}`, `%VAR%` and the built-in `<home>`, `<config>`,
+/// `<data>`, `<local_data>` and `<documents>` directories in a path
+pub fn expand_path(path: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        expanded.push_str(&builtin_dir("home")?);
+        rest = &rest[1..];
+    }
+
+    while let Some(start) = rest.find(['$', '%', '<']) {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (value, length) = match rest.as_bytes()[start] {
+            b'$' if after.starts_with('{') => match after.find('}') {
+                Some(end) => (env_var(&after[1..end])?, end + 1),
+                None => return Err(format!("unclosed ${{ in {}", path)),
+            },
+            b'$' => {
+                let end = after
+                    .find(|x: char| !x.is_ascii_alphanumeric() && x != '_')
+                    .unwrap_or(after.len());
+                if end == 0 {
+                    ("$".to_owned(), 0)
+                } else {
+                    (env_var(&after[..end])?, end)
+                }
+            }
+            b'%' => match after.find('%') {
+                Some(end) if end > 0 && !after[..end].contains(['/', '\\']) => {
+                    (env_var(&after[..end])?, end + 1)
+                }
+                _ => ("%".to_owned(), 0),
+            },
+            _ => match after.find('>') {
+                Some(end) => (builtin_dir(&after[..end])?, end + 1),
+                None => ("<".to_owned(), 0),
+            },
+        };
+        expanded.push_str(&value);
+        rest = &after[length..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn env_var(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|_| format!("environment variable {} is not set", name))
+}
+
+fn builtin_dir(name: &str) -> Result<String, String> {
+    let dir = match name {
+        "home" => dirs::home_dir(),
+        "config" => dirs::config_dir(),
+        "data" => dirs::data_dir(),
+        "local_data" => dirs::data_local_dir(),
+        "documents" => dirs::document_dir(),
+        _ => return Err(format!("unknown directory <{}>", name)),
+    };
+    dir.and_then(|x| x.to_str().map(|x| x.to_owned()))
+        .ok_or_else(|| format!("no {} directory found", name))
+}
+
+/// Flushes a written file to disk
+pub fn sync_file(path: &Path) -> io::Result<()> {
+    fs::File::options().write(true).open(path)?.sync_all()
+}
+
 #[cfg(test)]
 mod tests {
-    use std::time::SystemTime;
+    use std::{env, time::SystemTime};
 
-    use chrono::NaiveDateTime;
+    use chrono::DateTime;
+
+    use super::{expand_path, private_temp_path, remove_stale_temp_files, test_dir};
+
+    #[test]
+    fn test_expand_path() {
+        env::set_var("SAVE_BACKUP_TEST_DIR", "/games");
+        let home = dirs::home_dir().unwrap();
+        let home = home.to_str().unwrap();
+
+        assert_eq!(expand_path("~/saves").unwrap(), format!("{}/saves", home));
+        assert_eq!(
+            expand_path("<home>/saves").unwrap(),
+            format!("{}/saves", home)
+        );
+        assert_eq!(
+            expand_path("$SAVE_BACKUP_TEST_DIR/a/${SAVE_BACKUP_TEST_DIR}/%SAVE_BACKUP_TEST_DIR%")
+                .unwrap(),
+            "/games/a//games//games"
+        );
+        // Anything that isn't a variable is left alone
+        assert_eq!(expand_path("a/~/100%/$/b").unwrap(), "a/~/100%/$/b");
+        assert_eq!(expand_path("50%/saves%").unwrap(), "50%/saves%");
+        assert!(expand_path("%SAVE_BACKUP_UNSET_DIR%/saves").is_err());
+        assert!(expand_path("<nowhere>/saves").is_err());
+    }
 
     #[test]
     fn test_time() {
@@ -22,8 +227,45 @@ mod tests {
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        let other_time = NaiveDateTime::from_timestamp(time.try_into().unwrap(), 0);
+        let other_time = DateTime::from_timestamp(time.try_into().unwrap(), 0).unwrap();
         println!("{}", time);
         println!("{:?}", other_time);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_temp_path() {
+        use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+        let path = private_temp_path(Path::new("backups/thing.zip")).unwrap();
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .ends_with("-thing.zip"));
+        let mode = fs::metadata(path.parent().unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[test]
+    fn test_remove_stale_temp_files() {
+        use std::{fs, process};
+
+        let dir = test_dir("private_temp");
+        let own = dir.join(format!("{}-thing.zip", process::id()));
+        // Above the largest pid any platform hands out
+        let crashed = dir.join("1073741823-thing.zip");
+        let other = dir.join("notes.txt");
+        for path in [&own, &crashed, &other] {
+            fs::write(path, "").unwrap();
+        }
+        remove_stale_temp_files(&dir).unwrap();
+        assert!(own.exists());
+        assert!(!crashed.exists());
+        assert!(other.exists());
+        remove_stale_temp_files(&dir.join("missing")).unwrap();
+    }
 }
@@ -1,13 +1,75 @@
-use sysinfo::{ProcessExt, System, SystemExt};
+use std::{fs, path::Path};
 
-use super::log::LogExpectResult;
+use sysinfo::{Pid, ProcessExt, ProcessRefreshKind, RefreshKind, System, SystemExt};
 
-pub fn replace_instance() {
-    let s = System::new_all();
-    for process in s.processes_by_exact_name("save-backup.exe") {
-        let pid = sysinfo::get_current_pid().log_expect("Unsupported platform");
-        if process.pid() != pid {
-            process.kill();
+use super::{path::data_dir, utils::GenericResult};
+
+/// File in the data directory holding the pid of the running backup loop
+const PID_FILE: &str = "save-backup.pid";
+
+/// Closes the backup loop started before this one and records this process as the
+/// one to close next time. Commands never write the pid file, so they are left running.
+pub fn replace_instance() -> GenericResult<()> {
+    replace_instance_at(&data_dir().join(PID_FILE))
+}
+
+fn replace_instance_at(pid_file: &Path) -> GenericResult<()> {
+    let system = process_system();
+    let current = sysinfo::get_current_pid()?;
+    let previous = fs::read_to_string(pid_file)
+        .ok()
+        .and_then(|x| x.trim().parse::<Pid>().ok())
+        .filter(|x| *x != current);
+    if let Some(previous) = previous.and_then(|x| system.process(x)) {
+        // The pid may have been reused by another program since the file was written
+        let name = system.process(current).map(|x| x.name());
+        if name == Some(previous.name()) {
+            previous.kill();
         }
     }
+    fs::write(pid_file, current.to_string())?;
+    Ok(())
+}
+
+pub fn process_system() -> System {
+    System::new_with_specifics(RefreshKind::new().with_processes(ProcessRefreshKind::new()))
+}
+
+pub fn is_running(system: &mut System, name: &str) -> bool {
+    system.refresh_processes_specifics(ProcessRefreshKind::new());
+    system.processes_by_exact_name(name).next().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use sysinfo::{ProcessExt, SystemExt};
+
+    use crate::utils::{path::test_dir, utils::GenericResult};
+
+    use super::{is_running, process_system, replace_instance_at};
+
+    #[test]
+    fn test_is_running() {
+        let mut system = process_system();
+        let pid = sysinfo::get_current_pid().unwrap();
+        let name = system.process(pid).unwrap().name().to_owned();
+        assert!(is_running(&mut system, &name));
+        assert!(!is_running(&mut system, "not-a-running-process"));
+    }
+
+    #[test]
+    fn test_replace_instance() -> GenericResult<()> {
+        let pid_file = test_dir("process").join("save-backup.pid");
+        let pid = sysinfo::get_current_pid()?.to_string();
+        replace_instance_at(&pid_file)?;
+        assert_eq!(fs::read_to_string(&pid_file)?, pid);
+        // Never closes itself, and ignores a damaged file
+        replace_instance_at(&pid_file)?;
+        fs::write(&pid_file, "not a pid")?;
+        replace_instance_at(&pid_file)?;
+        assert_eq!(fs::read_to_string(&pid_file)?, pid);
+        Ok(())
+    }
 }
@@ -21,3 +21,19 @@ pub fn system_time_unix(time: &SystemTime) -> i64 {
         .try_into()
         .unwrap()
 }
+
+/// Formats a number of bytes with a 1024 based unit, such as `1.5 MiB`
+pub fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}